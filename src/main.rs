@@ -1,333 +1,6485 @@
 // SPDX-License-Identifier: BSD-3-Clause
 // Copyright (c) 2024 Hitalo M. <https://github.com/HitaloM>
 
-use rand::Rng;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, error, fmt, fs, io, mem, process};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
 use raylib::prelude::*;
 
-/// Constant defining the maximum length of the snake.
-const SNAKE_LENGTH: usize = 256;
+use transform::WorldTransform;
+
+mod transform;
 
-/// Constant defining the size of each square in the grid (both for the snake and fruit).
+/// Default size of each square in the grid (both for the snake and fruit), in pixels.
 const SQUARE_SIZE: i32 = 31;
 
-/// Structure representing the snake, containing its position, size, speed, and color.
-#[derive(Clone, Copy)]
-struct Snake {
-    position: Vector2,
-    size: Vector2,
-    speed: Vector2,
-    color: Color,
+/// Maximum number of direction changes buffered between two movement steps.
+const INPUT_QUEUE_LENGTH: usize = 3;
+
+/// Length of a simulation tick, in seconds.
+///
+/// Movement intervals, blinks and replays are all counted in ticks, so the game runs at the
+/// same speed whatever the frame rate.
+const TICK_DURATION: f32 = 1.0 / 60.0;
+
+/// Largest number of ticks run on a single frame to catch up after a slow one.
+const MAX_CATCH_UP_TICKS: u32 = 8;
+
+/// Smallest number of ticks between two movement steps, reached as the snake grows.
+const MIN_MOVE_INTERVAL: i32 = 2;
+
+/// Number of fruits to eat before the movement interval drops by one tick.
+const FRUITS_PER_LEVEL: i32 = 5;
+
+/// Radius of the eye whites drawn on the snake's head, in pixels at the default square size.
+const EYE_RADIUS: f32 = 5.0;
+
+/// Radius of the pupils drawn inside the eye whites, in pixels at the default square size.
+const PUPIL_RADIUS: f32 = 2.5;
+
+/// How far the pupils shift toward the fruit, in pixels at the default square size.
+const PUPIL_OFFSET: f32 = 2.0;
+
+/// Number of ticks between the start of two consecutive blinks.
+const BLINK_INTERVAL: i32 = 180;
+
+/// Number of ticks the eyes stay closed during a blink.
+const BLINK_DURATION: i32 = 8;
+
+/// Number of seconds of play kept in a clip.
+const CLIP_LENGTH: f32 = 30.0;
+
+/// Number of ticks between two snapshots of a run kept for clips.
+const CLIP_SNAPSHOT_TICKS: i32 = 300;
+
+/// Number of ticks the notice of a saved clip stays on screen.
+const CLIP_NOTICE_TICKS: i32 = 180;
+
+/// Version of the replay format, written on the first line of every replay file. Files written
+/// by earlier versions can still be read.
+const REPLAY_VERSION: u32 = 2;
+
+/// Number of seconds an error shown as a toast stays on screen.
+const TOAST_DURATION: f32 = 4.0;
+
+/// Music looping during a run when the config file does not name another track.
+const DEFAULT_MUSIC_PATH: &str = "assets/music.ogg";
+
+/// Short sound played when a run ends.
+const GAME_OVER_STING_PATH: &str = "assets/game-over.ogg";
+
+/// Amount the volume changes by with each press of `+` or `-`.
+const VOLUME_STEP: f32 = 0.1;
+
+/// Number of seconds the volume stays on screen after it changes.
+const VOLUME_NOTICE_DURATION: f32 = 1.5;
+
+/// Number of cells ahead of the starting cell kept free of the first fruit.
+const SPAWN_SAFE_LENGTH: i32 = 3;
+
+/// Number of cells ahead of the head covered by the danger prediction assist.
+const DANGER_LOOKAHEAD: usize = 3;
+
+/// Share of cells seeded as walls before a random arena is smoothed.
+const ARENA_WALL_DENSITY: f64 = 0.42;
+
+/// Number of smoothing passes run over a random arena.
+const ARENA_SMOOTHING_STEPS: usize = 3;
+
+/// Number of finished runs a new player's difficulty suggestion is based on.
+const SUGGESTION_RUNS: usize = 3;
+
+/// Share of a fruit's life left when the ring counting down to its expiry appears.
+const FRUIT_RING_SHARE: f32 = 0.25;
+
+/// Number of ticks the puff left by an expired fruit stays on screen.
+const FRUIT_PUFF_TICKS: i32 = 20;
+
+/// Column and row of a cell on the board, counted from the top-left corner.
+type Cell = (i32, i32);
+
+/// Most players a run can have.
+const MAX_PLAYERS: usize = 2;
+
+/// Colors the game is drawn with.
+#[derive(Clone, Copy, Debug)]
+struct Theme {
+    name: &'static str,                    // Name used in the config file
+    background: Color,                     // Color the window is cleared with
+    grid: Color,                           // Lines between the cells of the board
+    walls: Color,                          // Obstacles inside the board
+    snakes: [(Color, Color); MAX_PLAYERS], // Head and body colors of each snake, player one first
+    fruit: Color,                          // Fruit, kept apart from every snake color
+    text: Color,                           // Scores, headlines and indicators
+    muted_text: Color,                     // Prompts and notices
+    faint_text: Color,                     // Details such as the seed
 }
 
-/// Structure representing the food (fruit) in the game, containing its position, size, active state, and color.
-#[derive(Clone, Copy)]
-struct Food {
-    position: Vector2,
-    size: Vector2,
-    active: bool,
-    color: Color,
+/// Built-in themes, in the order the theme key cycles through them. The first is the default.
+const THEMES: [Theme; 3] = [
+    Theme {
+        name: "classic",
+        background: Color::RAYWHITE,
+        grid: Color::LIGHTGRAY,
+        walls: Color::DARKGRAY,
+        snakes: [
+            (Color::DARKBLUE, Color::BLUE),
+            (Color::DARKPURPLE, Color::PURPLE),
+        ],
+        fruit: Color::ORANGE,
+        text: Color::DARKGRAY,
+        muted_text: Color::GRAY,
+        faint_text: Color::LIGHTGRAY,
+    },
+    Theme {
+        name: "dark",
+        background: Color::new(24, 24, 32, 255),
+        grid: Color::new(52, 52, 64, 255),
+        walls: Color::GRAY,
+        snakes: [
+            (Color::SKYBLUE, Color::BLUE),
+            (Color::VIOLET, Color::PURPLE),
+        ],
+        fruit: Color::ORANGE,
+        text: Color::RAYWHITE,
+        muted_text: Color::LIGHTGRAY,
+        faint_text: Color::GRAY,
+    },
+    Theme {
+        name: "high-contrast",
+        background: Color::BLACK,
+        grid: Color::DARKGRAY,
+        walls: Color::WHITE,
+        snakes: [(Color::GREEN, Color::LIME), (Color::SKYBLUE, Color::BLUE)],
+        fruit: Color::MAGENTA,
+        text: Color::WHITE,
+        muted_text: Color::YELLOW,
+        faint_text: Color::LIGHTGRAY,
+    },
+];
+
+impl Theme {
+    /// Finds a built-in theme by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the theme, as used in the config file.
+    fn named(name: &str) -> Option<Theme> {
+        THEMES.iter().find(|theme| theme.name == name).copied()
+    }
+
+    /// Returns the built-in theme after this one, wrapping around to the first.
+    fn next(self) -> Theme {
+        let index = THEMES
+            .iter()
+            .position(|theme| theme.name == self.name)
+            .unwrap_or_default();
+        THEMES[(index + 1) % THEMES.len()]
+    }
 }
 
-/// Structure holding the entire game state, including the snake, food, and relevant game variables.
-struct GameState {
-    frames_counter: i32,                     // Counter to manage frame-based updates
-    game_over: bool,                         // Indicates if the game is over
-    pause: bool,                             // Indicates if the game is paused
-    allow_move: bool,                        // Allows movement control flag
-    counter_tail: usize,                     // Length of the snake's tail
-    offset: Vector2,                         // Offset for the snake's movement alignment
-    snake: [Snake; SNAKE_LENGTH],            // Array of snake segments
-    snake_position: [Vector2; SNAKE_LENGTH], // Array of snake segment positions
-    fruit: Food,                             // Represents the current fruit (food) in the game
+/// Phases the game can be in, each with its own update and draw behavior.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum GamePhase {
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+    Won,
 }
 
-impl GameState {
-    /// Creates a new game state with default initialization.
-    fn new() -> Self {
-        // Initialize snake with default values
-        let mut snake = [Snake {
-            position: Vector2::zero(),
-            size: Vector2::new(SQUARE_SIZE as f32, SQUARE_SIZE as f32),
-            speed: Vector2::new(SQUARE_SIZE as f32, 0.0),
-            color: Color::BLUE,
-        }; SNAKE_LENGTH];
-        snake[0].color = Color::DARKBLUE; // The head of the snake is a different color
+impl GamePhase {
+    /// Checks whether moving from this phase to `next` is a legal transition.
+    ///
+    /// # Arguments
+    ///
+    /// * `next` - The phase to move to.
+    fn can_transition_to(self, next: GamePhase) -> bool {
+        matches!(
+            (self, next),
+            (GamePhase::Title, GamePhase::Playing)
+                | (GamePhase::Playing, GamePhase::Paused)
+                | (GamePhase::Paused, GamePhase::Playing)
+                | (GamePhase::Playing, GamePhase::GameOver)
+                | (GamePhase::GameOver, GamePhase::Playing)
+                | (GamePhase::Playing, GamePhase::Won)
+                | (GamePhase::Won, GamePhase::Playing)
+        )
+    }
+}
 
-        GameState {
-            frames_counter: 0,
-            game_over: false,
-            pause: false,
-            allow_move: false,
-            counter_tail: 1,
-            offset: Vector2::zero(),
-            snake,
-            snake_position: [Vector2::zero(); SNAKE_LENGTH],
-            fruit: Food {
-                position: Vector2::zero(),
-                size: Vector2::new(SQUARE_SIZE as f32, SQUARE_SIZE as f32),
-                active: false,
-                color: Color::SKYBLUE,
-            },
+/// What happens when the snake's head leaves the board.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WallMode {
+    Solid, // Hitting a wall ends the game
+    Wrap,  // The head reappears on the opposite edge
+}
+
+impl WallMode {
+    /// Returns the other wall mode.
+    fn toggled(self) -> Self {
+        match self {
+            WallMode::Solid => WallMode::Wrap,
+            WallMode::Wrap => WallMode::Solid,
         }
     }
 
-    /// Initializes or resets the game state, placing the snake and fruit at their initial positions.
-    ///
-    /// # Arguments
+    /// Returns the name of the wall mode as shown on screen.
+    fn label(self) -> &'static str {
+        match self {
+            WallMode::Solid => "SOLID",
+            WallMode::Wrap => "WRAP",
+        }
+    }
+}
+
+/// Errors raised while loading, saving or parsing game data.
+#[derive(Debug)]
+enum SnakeError {
+    Io(io::Error), // The file could not be read or written
+    // A line of a file could not be understood, with lines counted from 1
+    Parse {
+        file: PathBuf,
+        line: usize,
+        msg: String,
+    },
+    // The file was written by another version of the game than the one this build reads
+    Version {
+        found: String,
+        supported: u32,
+    },
+    Validation(String), // A value was well-formed but not acceptable
+    Corrupt(PathBuf),   // The file exists but its contents could not be understood
+}
+
+/// How an error is shown in the game window.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Severity {
+    Toast,  // Shown for a few seconds while the game carries on
+    Dialog, // Stops the game until the player dismisses it
+}
+
+impl SnakeError {
+    /// Returns how the error is shown in the game window.
     ///
-    /// * `screen_width` - The width of the game window.
-    /// * `screen_height` - The height of the game window.
-    fn init_game(&mut self, screen_width: i32, screen_height: i32) {
-        self.frames_counter = 0;
-        self.game_over = false;
-        self.pause = false;
-        self.counter_tail = 1;
-        self.allow_move = false;
+    /// Failures the game can carry on from show as a toast, while a file the game will never
+    /// be able to use, which the player has to fix or remove, stops it behind a dialog.
+    fn severity(&self) -> Severity {
+        match self {
+            SnakeError::Io(_) | SnakeError::Parse { .. } | SnakeError::Validation(_) => {
+                Severity::Toast
+            }
+            SnakeError::Version { .. } | SnakeError::Corrupt(_) => Severity::Dialog,
+        }
+    }
+}
 
-        // Calculate offset to center snake on the screen
-        self.offset.x = (screen_width % SQUARE_SIZE) as f32;
-        self.offset.y = (screen_height % SQUARE_SIZE) as f32;
+impl fmt::Display for SnakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnakeError::Io(err) => write!(f, "{err}"),
+            SnakeError::Parse { file, line, msg } => write!(f, "{}:{line}: {msg}", file.display()),
+            SnakeError::Version { found, supported } => write!(
+                f,
+                "written by version {found} of the game, but only up to version {supported} is read"
+            ),
+            SnakeError::Validation(msg) => write!(f, "{msg}"),
+            SnakeError::Corrupt(path) => write!(f, "{} is corrupt", path.display()),
+        }
+    }
+}
 
-        // Initialize snake's position, size, speed, and color
-        for i in 0..SNAKE_LENGTH {
-            self.snake[i].position = Vector2::new(self.offset.x / 2.0, self.offset.y / 2.0);
-            self.snake[i].size = Vector2::new(SQUARE_SIZE as f32, SQUARE_SIZE as f32);
-            self.snake[i].speed = Vector2::new(SQUARE_SIZE as f32, 0.0);
-            self.snake[i].color = if i == 0 { Color::DARKBLUE } else { Color::BLUE };
+impl error::Error for SnakeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SnakeError::Io(err) => Some(err),
+            SnakeError::Parse { .. }
+            | SnakeError::Version { .. }
+            | SnakeError::Validation(_)
+            | SnakeError::Corrupt(_) => None,
         }
+    }
+}
+
+impl From<io::Error> for SnakeError {
+    fn from(err: io::Error) -> Self {
+        SnakeError::Io(err)
+    }
+}
+
+/// How a two-player run ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Outcome {
+    Winner(usize), // Index of the player left standing
+    Draw,          // Both players died on the same step
+}
+
+/// Why the fruit spawner turned down a cell, as shown by the trace overlay.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Rejection {
+    Occupied,   // A snake or an obstacle covers the cell
+    SafetyZone, // The cell lies in the spawn-safe zone kept free of the first fruit
+}
 
-        // Reset the snake's position history
-        for i in 0..SNAKE_LENGTH {
-            self.snake_position[i] = Vector2::zero();
+impl Rejection {
+    /// Returns the color the trace overlay outlines a cell rejected for this reason with.
+    fn color(self) -> Color {
+        match self {
+            Rejection::Occupied => Color::RED,
+            Rejection::SafetyZone => Color::ORANGE,
         }
+    }
+}
 
-        // Initialize fruit properties
-        self.fruit.size = Vector2::new(SQUARE_SIZE as f32, SQUARE_SIZE as f32);
-        self.fruit.color = Color::SKYBLUE;
-        self.fruit.active = false;
+/// Decisions recorded for the trace overlay, only filled in when tracing is enabled.
+#[derive(Default, Debug)]
+struct Trace {
+    candidates: Vec<Cell>,            // Cells the last fruit was picked from
+    rejected: Vec<(Cell, Rejection)>, // Cells the last fruit spawn turned down
+    unreachable: Vec<Cell>,           // Candidates of the last fruit spawn no head can reach
+    compared: Vec<Cell>, // Cells compared against the heads in the last collision check
+    exempted: Vec<Cell>, // Heads spared in the last collision check as a tail left their cell
+}
+
+/// Difficulty presets selectable at startup.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Returns the name of the difficulty as shown on screen.
+    fn label(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "EASY",
+            Difficulty::Normal => "NORMAL",
+            Difficulty::Hard => "HARD",
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = SnakeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(SnakeError::Validation(format!(
+                "unknown difficulty '{s}', expected easy, normal or hard"
+            ))),
+        }
     }
+}
+
+/// Settings that shape a run, kept across restarts.
+#[derive(Clone, Copy, Debug)]
+struct GameConfig {
+    difficulty: Difficulty,      // Preset the settings were derived from
+    start_move_interval: i32,    // Ticks between two movement steps at the start of a run
+    wall_mode: WallMode,         // Whether walls kill or wrap the snake
+    fruit_score: u32,            // Base number of points awarded for eating a fruit
+    two_player: bool,            // Adds a second snake controlled with the arrow keys
+    seed: Option<u64>,           // Seed of every run, or a fresh one per run when unset
+    trace_overlay: bool,         // Draws the spawn and collision decisions of the last tick
+    random_arena: bool,          // Fills the board with walls generated from the run's seed
+    fruit_lifetime: Option<i32>, // Ticks a fruit waits to be eaten before moving elsewhere
+}
 
-    /// Updates the game logic for each frame, including snake movement, fruit spawning, and collision detection.
+impl GameConfig {
+    /// Creates the configuration for a difficulty preset.
     ///
     /// # Arguments
     ///
-    /// * `rl` - The `RaylibHandle` used for input and rendering.
-    /// * `thread` - The `RaylibThread` required by the `raylib` API.
-    /// * `screen_width` - The width of the game window.
-    /// * `screen_height` - The height of the game window.
-    fn update_game(
-        &mut self,
-        rl: &mut RaylibHandle,
-        _thread: &RaylibThread,
-        screen_width: i32,
-        screen_height: i32,
-    ) {
-        if !self.game_over {
-            // Toggle pause state if 'P' is pressed
-            if rl.is_key_pressed(KeyboardKey::KEY_P) {
-                self.pause = !self.pause;
-            }
+    /// * `difficulty` - The preset to use.
+    fn new(difficulty: Difficulty) -> Self {
+        let (start_move_interval, wall_mode, fruit_score) = match difficulty {
+            Difficulty::Easy => (7, WallMode::Wrap, 5),
+            Difficulty::Normal => (6, WallMode::Solid, 10),
+            Difficulty::Hard => (3, WallMode::Solid, 20),
+        };
 
-            if !self.pause {
-                // Handle snake direction changes based on user input
-                if rl.is_key_pressed(KeyboardKey::KEY_D)
-                    && self.snake[0].speed.x == 0.0
-                    && self.allow_move
-                {
-                    self.snake[0].speed = Vector2::new(SQUARE_SIZE as f32, 0.0);
-                    self.allow_move = false;
-                }
-                if rl.is_key_pressed(KeyboardKey::KEY_A)
-                    && self.snake[0].speed.x == 0.0
-                    && self.allow_move
-                {
-                    self.snake[0].speed = Vector2::new(-SQUARE_SIZE as f32, 0.0);
-                    self.allow_move = false;
-                }
-                if rl.is_key_pressed(KeyboardKey::KEY_W)
-                    && self.snake[0].speed.y == 0.0
-                    && self.allow_move
-                {
-                    self.snake[0].speed = Vector2::new(0.0, -SQUARE_SIZE as f32);
-                    self.allow_move = false;
-                }
-                if rl.is_key_pressed(KeyboardKey::KEY_S)
-                    && self.snake[0].speed.y == 0.0
-                    && self.allow_move
-                {
-                    self.snake[0].speed = Vector2::new(0.0, SQUARE_SIZE as f32);
-                    self.allow_move = false;
-                }
+        GameConfig {
+            difficulty,
+            start_move_interval,
+            wall_mode,
+            fruit_score,
+            two_player: false,
+            seed: None,
+            trace_overlay: false,
+            random_arena: false,
+            fruit_lifetime: None,
+        }
+    }
+}
 
-                // Store the current positions of the snake
-                for i in 0..self.counter_tail {
-                    self.snake_position[i] = self.snake[i].position;
-                }
+/// Results of a finished solo run, kept to suggest a difficulty to new players.
+#[derive(Clone, Copy, Debug)]
+struct RunSummary {
+    seconds: f32,   // Time the run lasted, in seconds
+    length: usize,  // Length of the snake when the run ended
+    hit_wall: bool, // Whether the run ended by hitting a wall
+}
 
-                // Move the snake at specific frame intervals
-                if self.frames_counter % 5 == 0 {
-                    for i in (1..self.counter_tail).rev() {
-                        self.snake[i].position = self.snake_position[i - 1];
-                    }
-                    self.snake[0].position.x += self.snake[0].speed.x;
-                    self.snake[0].position.y += self.snake[0].speed.y;
-                    self.allow_move = true;
-                }
+/// Averages of the first runs that lead to suggesting a difficulty.
+struct SuggestionRule {
+    difficulty: Difficulty, // Difficulty to suggest
+    min_seconds: f32,       // Shortest average run, in seconds
+    max_seconds: f32,       // Longest average run, in seconds
+    min_length: f32,        // Shortest average snake length
+    min_wall_deaths: f32,   // Smallest share of runs ended by hitting a wall
+}
 
-                // Check for wall collisions
-                if self.snake[0].position.x > (screen_width as f32 - self.offset.x)
-                    || self.snake[0].position.y > (screen_height as f32 - self.offset.y)
-                    || self.snake[0].position.x < 0.0
-                    || self.snake[0].position.y < 0.0
-                {
-                    self.game_over = true;
-                }
+/// Rules checked in order against the first runs; the first one matching gives the suggestion.
+const SUGGESTION_RULES: [SuggestionRule; 3] = [
+    // Every run ends almost at once
+    SuggestionRule {
+        difficulty: Difficulty::Easy,
+        min_seconds: 0.0,
+        max_seconds: 15.0,
+        min_length: 0.0,
+        min_wall_deaths: 0.0,
+    },
+    // Short runs that keep ending at a wall, which Easy wraps around
+    SuggestionRule {
+        difficulty: Difficulty::Easy,
+        min_seconds: 0.0,
+        max_seconds: 30.0,
+        min_length: 0.0,
+        min_wall_deaths: 0.6,
+    },
+    // Long runs with a long snake
+    SuggestionRule {
+        difficulty: Difficulty::Hard,
+        min_seconds: 60.0,
+        max_seconds: f32::INFINITY,
+        min_length: 15.0,
+        min_wall_deaths: 0.0,
+    },
+];
 
-                // Check for self-collisions
-                for i in 1..self.counter_tail {
-                    if self.snake[0].position == self.snake[i].position {
-                        self.game_over = true;
-                    }
-                }
+/// Picks the difficulty to suggest from the averages of some runs, if any rule matches them.
+///
+/// # Arguments
+///
+/// * `runs` - The runs to look at.
+fn suggest_difficulty(runs: &[RunSummary]) -> Option<Difficulty> {
+    if runs.is_empty() {
+        return None;
+    }
 
-                // Spawn fruit in a random location if it's not active
-                if !self.fruit.active {
-                    self.fruit.active = true;
-                    self.fruit.position = Vector2::new(
-                        rand::thread_rng().gen_range(0..(screen_width / SQUARE_SIZE)) as f32
-                            * SQUARE_SIZE as f32
-                            + self.offset.x / 2.0,
-                        rand::thread_rng().gen_range(0..(screen_height / SQUARE_SIZE)) as f32
-                            * SQUARE_SIZE as f32
-                            + self.offset.y / 2.0,
-                    );
+    let count = runs.len() as f32;
+    let seconds = runs.iter().map(|run| run.seconds).sum::<f32>() / count;
+    let length = runs.iter().map(|run| run.length as f32).sum::<f32>() / count;
+    let wall_deaths = runs.iter().filter(|run| run.hit_wall).count() as f32 / count;
 
-                    // Ensure the fruit doesn't spawn on the snake
-                    for i in 0..self.counter_tail {
-                        while self.fruit.position == self.snake[i].position {
-                            self.fruit.position = Vector2::new(
-                                rand::thread_rng().gen_range(0..(screen_width / SQUARE_SIZE))
-                                    as f32
-                                    * SQUARE_SIZE as f32
-                                    + self.offset.x / 2.0,
-                                rand::thread_rng().gen_range(0..(screen_height / SQUARE_SIZE))
-                                    as f32
-                                    * SQUARE_SIZE as f32
-                                    + self.offset.y / 2.0,
-                            );
-                        }
-                    }
-                }
+    SUGGESTION_RULES
+        .iter()
+        .find(|rule| {
+            (rule.min_seconds..=rule.max_seconds).contains(&seconds)
+                && length >= rule.min_length
+                && wall_deaths >= rule.min_wall_deaths
+        })
+        .map(|rule| rule.difficulty)
+}
 
-                // Check for collisions between the snake's head and the fruit
-                if self.snake[0].position.x < (self.fruit.position.x + self.fruit.size.x)
-                    && self.snake[0].position.x + self.snake[0].size.x > self.fruit.position.x
-                    && self.snake[0].position.y < (self.fruit.position.y + self.fruit.size.y)
-                    && self.snake[0].position.y + self.snake[0].size.y > self.fruit.position.y
-                {
-                    self.snake[self.counter_tail].position =
-                        self.snake_position[self.counter_tail - 1];
-                    self.counter_tail += 1;
-                    self.fruit.active = false;
-                }
+/// Directions a snake can be steered in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
 
-                self.frames_counter += 1;
-            }
-        } else if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
-            self.init_game(screen_width, screen_height);
-            self.game_over = false;
+impl Direction {
+    /// Every direction, in the order the greedy bot breaks ties in.
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    /// Returns the cell `distance` cells away from `cell` in this direction.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to start from.
+    /// * `distance` - The number of cells to move by.
+    fn advance(self, (column, row): Cell, distance: i32) -> Cell {
+        match self {
+            Direction::Up => (column, row - distance),
+            Direction::Down => (column, row + distance),
+            Direction::Left => (column - distance, row),
+            Direction::Right => (column + distance, row),
         }
     }
 
-    /// Draws the game elements, including the grid, snake, fruit, and game over screen.
+    /// Returns the direction pointing the other way.
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// Returns the direction a quarter turn clockwise from this one, as seen on screen.
+    fn clockwise(self) -> Self {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    /// Returns the direction a quarter turn counter-clockwise from this one, as seen on screen.
+    fn counter_clockwise(self) -> Self {
+        self.clockwise().opposite()
+    }
+
+    /// Checks whether the direction runs along a row.
+    fn is_horizontal(self) -> bool {
+        matches!(self, Direction::Left | Direction::Right)
+    }
+
+    /// Returns the unit vector pointing in this direction in screen space.
+    fn vector(self) -> Vector2 {
+        match self {
+            Direction::Up => Vector2::new(0.0, -1.0),
+            Direction::Down => Vector2::new(0.0, 1.0),
+            Direction::Left => Vector2::new(-1.0, 0.0),
+            Direction::Right => Vector2::new(1.0, 0.0),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Direction {
+    type Err = SnakeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            _ => Err(SnakeError::Validation(format!(
+                "unknown direction '{s}', expected up, down, left or right"
+            ))),
+        }
+    }
+}
+
+/// Names of the keys that can be bound in the config file, as written there and on screen.
+const KEY_NAMES: [(&str, KeyboardKey); 44] = [
+    ("A", KeyboardKey::KEY_A),
+    ("B", KeyboardKey::KEY_B),
+    ("C", KeyboardKey::KEY_C),
+    ("D", KeyboardKey::KEY_D),
+    ("E", KeyboardKey::KEY_E),
+    ("F", KeyboardKey::KEY_F),
+    ("G", KeyboardKey::KEY_G),
+    ("H", KeyboardKey::KEY_H),
+    ("I", KeyboardKey::KEY_I),
+    ("J", KeyboardKey::KEY_J),
+    ("K", KeyboardKey::KEY_K),
+    ("L", KeyboardKey::KEY_L),
+    ("M", KeyboardKey::KEY_M),
+    ("N", KeyboardKey::KEY_N),
+    ("O", KeyboardKey::KEY_O),
+    ("P", KeyboardKey::KEY_P),
+    ("Q", KeyboardKey::KEY_Q),
+    ("R", KeyboardKey::KEY_R),
+    ("S", KeyboardKey::KEY_S),
+    ("T", KeyboardKey::KEY_T),
+    ("U", KeyboardKey::KEY_U),
+    ("V", KeyboardKey::KEY_V),
+    ("W", KeyboardKey::KEY_W),
+    ("X", KeyboardKey::KEY_X),
+    ("Y", KeyboardKey::KEY_Y),
+    ("Z", KeyboardKey::KEY_Z),
+    ("0", KeyboardKey::KEY_ZERO),
+    ("1", KeyboardKey::KEY_ONE),
+    ("2", KeyboardKey::KEY_TWO),
+    ("3", KeyboardKey::KEY_THREE),
+    ("4", KeyboardKey::KEY_FOUR),
+    ("5", KeyboardKey::KEY_FIVE),
+    ("6", KeyboardKey::KEY_SIX),
+    ("7", KeyboardKey::KEY_SEVEN),
+    ("8", KeyboardKey::KEY_EIGHT),
+    ("9", KeyboardKey::KEY_NINE),
+    ("UP", KeyboardKey::KEY_UP),
+    ("DOWN", KeyboardKey::KEY_DOWN),
+    ("LEFT", KeyboardKey::KEY_LEFT),
+    ("RIGHT", KeyboardKey::KEY_RIGHT),
+    ("ENTER", KeyboardKey::KEY_ENTER),
+    ("SPACE", KeyboardKey::KEY_SPACE),
+    ("TAB", KeyboardKey::KEY_TAB),
+    ("BACKSPACE", KeyboardKey::KEY_BACKSPACE),
+];
+
+/// Returns the name of a key, as written in the config file and on screen.
+///
+/// # Arguments
+///
+/// * `key` - The key to name.
+fn key_name(key: KeyboardKey) -> &'static str {
+    KEY_NAMES
+        .iter()
+        .find(|&&(_, named)| named == key)
+        .map_or("?", |&(name, _)| name)
+}
+
+/// Keys bound to the actions of the game, which the `[keys]` section of the config file can
+/// change. The volume keys and the digits typing a seed are fixed.
+///
+/// Two actions may only share a key if no screen reads both, as steering down and saving the
+/// replay do by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KeyBindings {
+    steer: [[KeyboardKey; 4]; MAX_PLAYERS], // Keys steering each player, in `Direction::ALL` order
+    pause: KeyboardKey,                     // Toggles the pause
+    restart: KeyboardKey,                   // Starts a run, or dismisses an error
+    toggle_assist: KeyboardKey,             // Toggles the danger prediction assist
+    toggle_auto_turn: KeyboardKey,          // Toggles the auto-turn at walls assist
+    toggle_walls: KeyboardKey,              // Switches the wall mode while paused
+    toggle_indicator: KeyboardKey,          // Toggles the input timing indicator
+    save_replay: KeyboardKey,               // Saves the replay of the run that just ended
+    save_clip: KeyboardKey,                 // Saves the last seconds of the run in progress
+    cycle_theme: KeyboardKey,               // Switches to the next color theme
+    toggle_shape_cues: KeyboardKey,         // Toggles the fruit and head shape cues
+    accept_suggestion: KeyboardKey,         // Makes the suggested difficulty the default
+    reroll_arena: KeyboardKey,              // Generates another random arena on the title screen
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            steer: [
+                [
+                    KeyboardKey::KEY_W,
+                    KeyboardKey::KEY_S,
+                    KeyboardKey::KEY_A,
+                    KeyboardKey::KEY_D,
+                ],
+                [
+                    KeyboardKey::KEY_UP,
+                    KeyboardKey::KEY_DOWN,
+                    KeyboardKey::KEY_LEFT,
+                    KeyboardKey::KEY_RIGHT,
+                ],
+            ],
+            pause: KeyboardKey::KEY_P,
+            restart: KeyboardKey::KEY_ENTER,
+            toggle_assist: KeyboardKey::KEY_H,
+            toggle_auto_turn: KeyboardKey::KEY_T,
+            toggle_walls: KeyboardKey::KEY_M,
+            toggle_indicator: KeyboardKey::KEY_I,
+            save_replay: KeyboardKey::KEY_S,
+            save_clip: KeyboardKey::KEY_C,
+            cycle_theme: KeyboardKey::KEY_L,
+            toggle_shape_cues: KeyboardKey::KEY_G,
+            accept_suggestion: KeyboardKey::KEY_Y,
+            reroll_arena: KeyboardKey::KEY_R,
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Names of the actions in the `[keys]` section of the config file, in template order.
+    const ACTIONS: [&'static str; 20] = [
+        "up",
+        "down",
+        "left",
+        "right",
+        "player_two_up",
+        "player_two_down",
+        "player_two_left",
+        "player_two_right",
+        "pause",
+        "restart",
+        "assist",
+        "auto_turn",
+        "walls",
+        "indicator",
+        "save_replay",
+        "save_clip",
+        "theme",
+        "shape_cues",
+        "accept_suggestion",
+        "reroll_arena",
+    ];
+
+    /// Keys typing the seed of a random arena on the title screen, which no action can take.
+    const RESERVED: [KeyboardKey; 11] = [
+        KeyboardKey::KEY_ZERO,
+        KeyboardKey::KEY_ONE,
+        KeyboardKey::KEY_TWO,
+        KeyboardKey::KEY_THREE,
+        KeyboardKey::KEY_FOUR,
+        KeyboardKey::KEY_FIVE,
+        KeyboardKey::KEY_SIX,
+        KeyboardKey::KEY_SEVEN,
+        KeyboardKey::KEY_EIGHT,
+        KeyboardKey::KEY_NINE,
+        KeyboardKey::KEY_BACKSPACE,
+    ];
+
+    /// Screens an action is read on, as flags that can be combined.
+    const ON_TITLE: u8 = 1;
+    const ON_RUN: u8 = 2;
+    const ON_END: u8 = 4;
+
+    /// Returns the screens an action named as in the config file is read on.
     ///
     /// # Arguments
     ///
-    /// * `d` - The `RaylibDrawHandle` used for rendering.
-    fn draw_game(&self, d: &mut RaylibDrawHandle) {
-        d.clear_background(Color::RAYWHITE);
+    /// * `action` - The name of the action.
+    fn screens(action: &str) -> u8 {
+        match action {
+            "restart" => Self::ON_TITLE | Self::ON_END,
+            "save_replay" | "accept_suggestion" => Self::ON_END,
+            "theme" | "shape_cues" => Self::ON_TITLE | Self::ON_RUN | Self::ON_END,
+            "reroll_arena" => Self::ON_TITLE,
+            _ => Self::ON_RUN,
+        }
+    }
 
-        // Draw game elements if the game is not over
-        if !self.game_over {
-            for i in 0..(800 / SQUARE_SIZE + 1) {
-                d.draw_line_v(
-                    Vector2::new(
-                        SQUARE_SIZE as f32 * i as f32 + self.offset.x / 2.0,
-                        self.offset.y / 2.0,
-                    ),
-                    Vector2::new(
-                        SQUARE_SIZE as f32 * i as f32 + self.offset.x / 2.0,
-                        450.0 - self.offset.y / 2.0,
-                    ),
-                    Color::LIGHTGRAY,
-                );
-            }
-            for i in 0..(450 / SQUARE_SIZE + 1) {
-                d.draw_line_v(
-                    Vector2::new(
-                        self.offset.x / 2.0,
-                        SQUARE_SIZE as f32 * i as f32 + self.offset.y / 2.0,
-                    ),
-                    Vector2::new(
-                        800.0 - self.offset.x / 2.0,
-                        SQUARE_SIZE as f32 * i as f32 + self.offset.y / 2.0,
-                    ),
-                    Color::LIGHTGRAY,
-                );
-            }
+    /// Returns the key bound to an action named as in the config file.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    fn key(&self, action: &str) -> Option<KeyboardKey> {
+        let mut keys = *self;
+        keys.key_mut(action).map(|key| *key)
+    }
 
-            // Draw the snake and fruit
-            for i in 0..self.counter_tail {
-                d.draw_rectangle_v(
-                    self.snake[i].position,
-                    self.snake[i].size,
-                    self.snake[i].color,
-                );
-            }
+    /// Returns another action read on a screen with an action and bound to the same key, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    fn clash(&self, action: &str) -> Option<&'static str> {
+        let key = self.key(action)?;
+        Self::ACTIONS.into_iter().find(|&other| {
+            other != action
+                && self.key(other) == Some(key)
+                && Self::screens(other) & Self::screens(action) != 0
+        })
+    }
 
-            d.draw_rectangle_v(self.fruit.position, self.fruit.size, self.fruit.color);
+    /// Returns the key bound to an action named as in the config file, so it can be changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The name of the action.
+    fn key_mut(&mut self, action: &str) -> Option<&mut KeyboardKey> {
+        Some(match action {
+            "up" => &mut self.steer[0][0],
+            "down" => &mut self.steer[0][1],
+            "left" => &mut self.steer[0][2],
+            "right" => &mut self.steer[0][3],
+            "player_two_up" => &mut self.steer[1][0],
+            "player_two_down" => &mut self.steer[1][1],
+            "player_two_left" => &mut self.steer[1][2],
+            "player_two_right" => &mut self.steer[1][3],
+            "pause" => &mut self.pause,
+            "restart" => &mut self.restart,
+            "assist" => &mut self.toggle_assist,
+            "auto_turn" => &mut self.toggle_auto_turn,
+            "walls" => &mut self.toggle_walls,
+            "indicator" => &mut self.toggle_indicator,
+            "save_replay" => &mut self.save_replay,
+            "save_clip" => &mut self.save_clip,
+            "theme" => &mut self.cycle_theme,
+            "shape_cues" => &mut self.toggle_shape_cues,
+            "accept_suggestion" => &mut self.accept_suggestion,
+            "reroll_arena" => &mut self.reroll_arena,
+            _ => return None,
+        })
+    }
 
-            // Draw the game over message if the game is over
-            if self.pause {
-                d.draw_text(
-                    "GAME PAUSED",
-                    800 / 2 - d.measure_text("GAME PAUSED", 40) / 2,
-                    450 / 2 - 40,
-                    40,
-                    Color::GRAY,
-                );
-            }
-        } else {
-            d.draw_text(
-                "PRESS [ENTER] TO PLAY AGAIN",
-                800 / 2 - d.measure_text("PRESS [ENTER] TO PLAY AGAIN", 20) / 2,
-                450 / 2 - 50,
-                20,
-                Color::GRAY,
-            );
+    /// Returns the keys steering a player as shown on screen, such as `[W][A][S][D]`.
+    ///
+    /// # Arguments
+    ///
+    /// * `player` - The index of the player.
+    fn steer_label(&self, player: usize) -> String {
+        let [up, down, left, right] = self.steer[player];
+        [up, left, down, right]
+            .map(|key| format!("[{}]", key_name(key)))
+            .concat()
+    }
+}
+
+/// Inputs gathered for a single frame, so the game logic can run without a window.
+#[derive(Clone, Default, Debug)]
+struct InputState {
+    directions: [Vec<Direction>; 2], // Directions pressed by each player, player one first
+    pause: bool,                     // Toggles the pause
+    restart: bool,                   // Starts a run from the title or end screens
+    toggle_assist: bool,             // Toggles the danger prediction assist
+    toggle_auto_turn: bool,          // Toggles the auto-turn at walls assist
+    toggle_walls: bool,              // Switches the wall mode while paused
+    toggle_indicator: bool,          // Toggles the input timing indicator
+    volume_up: bool,                 // Turns the volume up
+    volume_down: bool,               // Turns the volume down
+    save_replay: bool,               // Saves the replay of the run that just ended
+    save_clip: bool,                 // Saves the last seconds of the run in progress
+    cycle_theme: bool,               // Switches to the next color theme
+    toggle_shape_cues: bool,         // Toggles the fruit and head shape cues
+    accept_suggestion: bool,         // Makes the suggested difficulty the default
+    reroll_arena: bool,              // Generates another random arena on the title screen
+    seed_digit: Option<u64>,         // Digit typed toward the seed of a random arena
+    erase_seed_digit: bool,          // Erases the last digit of the seed being typed
+}
+
+impl InputState {
+    /// Reads the inputs pressed this frame from the keyboard.
+    ///
+    /// Player one steers with WASD and player two with the arrow keys, unless the config file
+    /// binds other keys. Every direction pressed on the frame is kept, for `resolve_chord` to
+    /// sort out.
+    ///
+    /// # Arguments
+    ///
+    /// * `rl` - The `RaylibHandle` used for input.
+    /// * `keys` - The keys bound to each action.
+    fn read(rl: &RaylibHandle, keys: &KeyBindings) -> Self {
+        let digits = [
+            KeyboardKey::KEY_ZERO,
+            KeyboardKey::KEY_ONE,
+            KeyboardKey::KEY_TWO,
+            KeyboardKey::KEY_THREE,
+            KeyboardKey::KEY_FOUR,
+            KeyboardKey::KEY_FIVE,
+            KeyboardKey::KEY_SIX,
+            KeyboardKey::KEY_SEVEN,
+            KeyboardKey::KEY_EIGHT,
+            KeyboardKey::KEY_NINE,
+        ];
+
+        InputState {
+            directions: keys.steer.map(|steer| {
+                Direction::ALL
+                    .into_iter()
+                    .zip(steer)
+                    .filter(|&(_, key)| rl.is_key_pressed(key))
+                    .map(|(direction, _)| direction)
+                    .collect()
+            }),
+            pause: rl.is_key_pressed(keys.pause),
+            restart: rl.is_key_pressed(keys.restart),
+            toggle_assist: rl.is_key_pressed(keys.toggle_assist),
+            toggle_auto_turn: rl.is_key_pressed(keys.toggle_auto_turn),
+            toggle_walls: rl.is_key_pressed(keys.toggle_walls),
+            toggle_indicator: rl.is_key_pressed(keys.toggle_indicator),
+            volume_up: rl.is_key_pressed(KeyboardKey::KEY_EQUAL)
+                || rl.is_key_pressed(KeyboardKey::KEY_KP_ADD),
+            volume_down: rl.is_key_pressed(KeyboardKey::KEY_MINUS)
+                || rl.is_key_pressed(KeyboardKey::KEY_KP_SUBTRACT),
+            save_replay: rl.is_key_pressed(keys.save_replay),
+            save_clip: rl.is_key_pressed(keys.save_clip),
+            cycle_theme: rl.is_key_pressed(keys.cycle_theme),
+            toggle_shape_cues: rl.is_key_pressed(keys.toggle_shape_cues),
+            accept_suggestion: rl.is_key_pressed(keys.accept_suggestion),
+            reroll_arena: rl.is_key_pressed(keys.reroll_arena),
+            seed_digit: (0..)
+                .zip(digits)
+                .find(|&(_, key)| rl.is_key_pressed(key))
+                .map(|(digit, _)| digit),
+            erase_seed_digit: rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE),
         }
     }
 }
 
-/// Main function to initialize the game window and run the game loop.
-fn main() {
-    let screen_width = 800;
-    let screen_height = 450;
+/// Turns the directions a player pressed on the same frame into the turns to queue, in order.
+///
+/// Opposite directions cancel each other out. Of two perpendicular ones, the one turning off
+/// `heading` goes first, so pressing up and left while heading right makes a tight turn up
+/// and then left instead of depending on which key was read first.
+///
+/// # Arguments
+///
+/// * `heading` - The direction the snake will be heading in when the turns are applied.
+/// * `pressed` - The directions pressed on the frame.
+fn resolve_chord(heading: Direction, pressed: &[Direction]) -> Vec<Direction> {
+    let mut turns: Vec<Direction> = pressed
+        .iter()
+        .copied()
+        .filter(|&direction| !pressed.contains(&direction.opposite()))
+        .collect();
+    turns.sort_by_key(|&direction| direction.is_horizontal() == heading.is_horizontal());
+    turns
+}
 
-    let (mut rl, thread) = raylib::init()
-        .size(screen_width, screen_height)
-        .title("snake")
-        .build();
+/// Something that steers player one's snake, such as the keyboard or a bot.
+trait Controller {
+    /// Picks the directions to steer in on this tick, in the order they are queued, or none to
+    /// keep the current heading.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The game being played.
+    /// * `input` - The inputs read from the keyboard since the last tick.
+    fn decide(&mut self, state: &GameState, input: &InputState) -> Vec<Direction>;
+}
 
-    rl.set_target_fps(60);
+/// Controller steering with the keys pressed by player one.
+struct Keyboard;
 
-    let mut game_state = GameState::new();
-    game_state.init_game(screen_width, screen_height);
+impl Controller for Keyboard {
+    fn decide(&mut self, _state: &GameState, input: &InputState) -> Vec<Direction> {
+        input.directions[0].clone()
+    }
+}
 
-    while !rl.window_should_close() {
-        game_state.update_game(&mut rl, &thread, screen_width, screen_height);
+/// Controller replaying the directions of an input script, keyed by tick number.
+struct Script {
+    directions: HashMap<u32, Direction>, // Direction pressed on each scripted tick
+}
 
-        let mut d = rl.begin_drawing(&thread);
-        game_state.draw_game(&mut d);
+impl Controller for Script {
+    fn decide(&mut self, state: &GameState, _input: &InputState) -> Vec<Direction> {
+        self.directions
+            .get(&(state.tick_counter as u32))
+            .copied()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Controller heading straight for the fruit while steering clear of deadly cells.
+struct GreedyBot;
+
+impl Controller for GreedyBot {
+    fn decide(&mut self, state: &GameState, _input: &InputState) -> Vec<Direction> {
+        // Only decide right before a movement step, when the heading is up to date
+        if state.tick_counter % state.move_interval() != 0 {
+            return Vec::new();
+        }
+
+        let head = state.players[0].snake[0];
+        let next_cell = |direction: Direction| {
+            let cell = direction.advance(head.position, 1);
+            match state.config.wall_mode {
+                WallMode::Solid => cell,
+                WallMode::Wrap => state.wrap_position(cell),
+            }
+        };
+        // Straight ahead first, so ties keep the current heading
+        let mut options: Vec<Direction> = Direction::ALL
+            .into_iter()
+            .filter(|&direction| direction != head.direction.opposite())
+            .filter(|&direction| !state.is_deadly(next_cell(direction), 1))
+            .collect();
+        options.sort_by_key(|&direction| direction != head.direction);
+
+        // Before the first fruit spawns there is nothing to chase
+        if !state.fruit.active {
+            options.truncate(1);
+            return options;
+        }
+
+        options
+            .into_iter()
+            .min_by_key(|&direction| {
+                let (column, row) = next_cell(direction);
+                (column - state.fruit.position.0).abs() + (row - state.fruit.position.1).abs()
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Inputs that change how a run plays out, as stored in a replay.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ReplayEvent {
+    Turn(usize, Direction), // A player's direction change was accepted
+    Pause,                  // The game was paused, dropping the buffered direction changes
+    ToggleWalls,            // The wall mode was switched
+    ToggleAutoTurn,         // The auto-turn at walls assist was toggled
+}
+
+/// A recorded run: the settings it started from and every input that shaped it.
+#[derive(Clone, Debug)]
+struct Replay {
+    config: GameConfig,              // Settings of the run, its seed included
+    auto_turn: bool,                 // Whether the auto-turn at walls assist was on at the start
+    level: Option<PathBuf>,          // Level file the run was played on
+    board: (i32, i32),               // Number of columns and rows of the board
+    clip: Option<(i32, i32)>,        // First and last tick shown when the replay is a clip
+    snapshot: Option<Snapshot>,      // State the replay starts from, if not the start of the run
+    events: Vec<(i32, ReplayEvent)>, // Inputs in order, with the tick they were applied on
+}
+
+impl Replay {
+    /// Creates an empty replay for a run about to start.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings of the run, its seed included.
+    /// * `auto_turn` - Whether the auto-turn at walls assist is on.
+    /// * `level` - The level file the run is played on, if any.
+    /// * `board` - The number of columns and rows of the board.
+    fn new(config: GameConfig, auto_turn: bool, level: Option<PathBuf>, board: (i32, i32)) -> Self {
+        Replay {
+            config,
+            auto_turn,
+            level,
+            board,
+            clip: None,
+            snapshot: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Writes the replay as text, one setting or event per line.
+    fn to_text(&self) -> String {
+        let mut text = format!("snake-replay {REPLAY_VERSION}\n");
+        text += &format!(
+            "difficulty {}\n",
+            self.config.difficulty.label().to_ascii_lowercase()
+        );
+        text += &format!(
+            "walls {}\n",
+            self.config.wall_mode.label().to_ascii_lowercase()
+        );
+        text += &format!("two-player {}\n", self.config.two_player);
+        text += &format!("random-arena {}\n", self.config.random_arena);
+        if let Some(lifetime) = self.config.fruit_lifetime {
+            text += &format!("fruit-lifetime {lifetime}\n");
+        }
+        text += &format!("auto-turn {}\n", self.auto_turn);
+        text += &format!("seed {}\n", self.config.seed.unwrap_or_default());
+        text += &format!("board {} {}\n", self.board.0, self.board.1);
+        if let Some((start, end)) = self.clip {
+            text += &format!("clip {start} {end}\n");
+        }
+        if let Some(level) = &self.level {
+            text += &format!("level {}\n", level.display());
+        }
+        if let Some(snapshot) = &self.snapshot {
+            text += &snapshot.to_text();
+        }
+
+        for &(frame, event) in &self.events {
+            text += &match event {
+                ReplayEvent::Turn(player, direction) => {
+                    format!("{frame} turn {player} {direction}\n")
+                }
+                ReplayEvent::Pause => format!("{frame} pause\n"),
+                ReplayEvent::ToggleWalls => format!("{frame} walls\n"),
+                ReplayEvent::ToggleAutoTurn => format!("{frame} auto-turn\n"),
+            };
+        }
+
+        text
+    }
+
+    /// Reads a replay written by `to_text`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The contents of the replay file.
+    /// * `path` - The path of the file, used in error messages.
+    fn parse(text: &str, path: &Path) -> Result<Self, SnakeError> {
+        let error_at = |number: usize, msg: String| SnakeError::Parse {
+            file: path.to_path_buf(),
+            line: number + 1,
+            msg,
+        };
+
+        let mut lines = text.lines().enumerate();
+        let header = lines
+            .next()
+            .map(|(_, line)| line.trim())
+            .unwrap_or_default();
+        match header.strip_prefix("snake-replay ") {
+            Some(version)
+                if version
+                    .parse()
+                    .is_ok_and(|version: u32| (1..=REPLAY_VERSION).contains(&version)) => {}
+            Some(version) => {
+                return Err(SnakeError::Version {
+                    found: version.to_string(),
+                    supported: REPLAY_VERSION,
+                })
+            }
+            None => return Err(error_at(0, "not a replay file".to_string())),
+        }
+
+        let mut difficulty = Difficulty::Normal;
+        let mut wall_mode = None;
+        let mut two_player = false;
+        let mut random_arena = false;
+        let mut fruit_lifetime = None;
+        let mut auto_turn = false;
+        let mut seed = None;
+        let mut level = None;
+        // Replays written before the board size could change were all played on 25x14
+        let mut board = (25, 14);
+        let mut clip = None;
+        let mut snapshot: Option<Snapshot> = None;
+        let mut events: Vec<(i32, ReplayEvent)> = Vec::new();
+
+        for (number, line) in lines {
+            let invalid = || error_at(number, "invalid entry".to_string());
+            let at_line = |err: SnakeError| error_at(number, err.to_string());
+            let flag = |value: &str| value.parse::<bool>().map_err(|_| invalid());
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            match fields.as_slice() {
+                [] => {}
+                ["difficulty", value] => difficulty = value.parse().map_err(at_line)?,
+                ["walls", "solid"] => wall_mode = Some(WallMode::Solid),
+                ["walls", "wrap"] => wall_mode = Some(WallMode::Wrap),
+                ["two-player", value] => two_player = flag(value)?,
+                ["random-arena", value] => random_arena = flag(value)?,
+                ["fruit-lifetime", value] => {
+                    let lifetime = value
+                        .parse()
+                        .ok()
+                        .filter(|&lifetime: &i32| lifetime > 0)
+                        .ok_or_else(invalid)?;
+                    fruit_lifetime = Some(lifetime);
+                }
+                ["auto-turn", value] => auto_turn = flag(value)?,
+                ["seed", value] => seed = Some(value.parse().map_err(|_| invalid())?),
+                ["level", ..] => level = Some(PathBuf::from(line.trim()["level".len()..].trim())),
+                ["board", columns, rows] => {
+                    let size = |value: &str| {
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&size: &i32| size > 0)
+                            .ok_or_else(invalid)
+                    };
+                    board = (size(columns)?, size(rows)?);
+                }
+                ["clip", start, end] => {
+                    let start: i32 = start.parse().map_err(|_| invalid())?;
+                    let end: i32 = end.parse().map_err(|_| invalid())?;
+                    if start < 0 || end < start {
+                        return Err(invalid());
+                    }
+                    clip = Some((start, end));
+                }
+                ["snapshot", tick, draws, walls, auto_turn] => {
+                    snapshot = Some(Snapshot {
+                        tick: tick
+                            .parse()
+                            .ok()
+                            .filter(|&tick: &i32| tick >= 0)
+                            .ok_or_else(invalid)?,
+                        draws: draws.parse().map_err(|_| invalid())?,
+                        wall_mode: match *walls {
+                            "solid" => WallMode::Solid,
+                            "wrap" => WallMode::Wrap,
+                            _ => return Err(invalid()),
+                        },
+                        auto_turn: flag(auto_turn)?,
+                        snakes: Vec::new(),
+                        fruit: None,
+                    });
+                }
+                ["snake", index, score, direction, cells @ ..] => {
+                    let snapshot = snapshot.as_mut().ok_or_else(invalid)?;
+                    if *index != snapshot.snakes.len().to_string() || cells.is_empty() {
+                        return Err(invalid());
+                    }
+                    snapshot.snakes.push(SnakeSnapshot {
+                        cells: cells
+                            .iter()
+                            .map(|cell| parse_cell(cell).ok_or_else(invalid))
+                            .collect::<Result<_, _>>()?,
+                        direction: direction.parse().map_err(at_line)?,
+                        queue: Vec::new(),
+                        score: score.parse().map_err(|_| invalid())?,
+                    });
+                }
+                ["queue", index, directions @ ..] => {
+                    let snake = snapshot
+                        .as_mut()
+                        .and_then(|snapshot| snapshot.snakes.get_mut(index.parse::<usize>().ok()?))
+                        .ok_or_else(invalid)?;
+                    snake.queue = directions
+                        .iter()
+                        .map(|direction| direction.parse().map_err(at_line))
+                        .collect::<Result<_, _>>()?;
+                }
+                ["fruit", column, row, age] => {
+                    let snapshot = snapshot.as_mut().ok_or_else(invalid)?;
+                    let cell = (
+                        column.parse().map_err(|_| invalid())?,
+                        row.parse().map_err(|_| invalid())?,
+                    );
+                    snapshot.fruit = Some((cell, age.parse().map_err(|_| invalid())?));
+                }
+                [frame, event @ ..] => {
+                    let frame: i32 = frame.parse().map_err(|_| invalid())?;
+                    let event = match event {
+                        ["turn", player, direction] => {
+                            let player = player
+                                .parse()
+                                .ok()
+                                .filter(|&player: &usize| player < MAX_PLAYERS)
+                                .ok_or_else(invalid)?;
+                            ReplayEvent::Turn(player, direction.parse().map_err(at_line)?)
+                        }
+                        ["pause"] => ReplayEvent::Pause,
+                        ["walls"] => ReplayEvent::ToggleWalls,
+                        ["auto-turn"] => ReplayEvent::ToggleAutoTurn,
+                        _ => return Err(invalid()),
+                    };
+                    if events.last().is_some_and(|&(last, _)| frame < last) {
+                        return Err(error_at(number, "events are out of order".to_string()));
+                    }
+                    events.push((frame, event));
+                }
+            }
+        }
+
+        let seed =
+            seed.ok_or_else(|| SnakeError::Validation(format!("{} has no seed", path.display())))?;
+        let players = if two_player { 2 } else { 1 };
+        if let Some(snapshot) = &snapshot {
+            if snapshot.snakes.len() != players {
+                return Err(SnakeError::Validation(format!(
+                    "{} has a snapshot of {} snakes for {players} players",
+                    path.display(),
+                    snapshot.snakes.len()
+                )));
+            }
+            if events
+                .first()
+                .is_some_and(|&(frame, _)| frame < snapshot.tick)
+            {
+                return Err(SnakeError::Validation(format!(
+                    "{} has events from before its snapshot",
+                    path.display()
+                )));
+            }
+        }
+        let preset = GameConfig::new(difficulty);
+        let config = GameConfig {
+            wall_mode: wall_mode.unwrap_or(preset.wall_mode),
+            two_player,
+            random_arena,
+            fruit_lifetime,
+            seed: Some(seed),
+            ..preset
+        };
+        Ok(Replay {
+            clip,
+            snapshot,
+            events,
+            ..Replay::new(config, auto_turn, level, board)
+        })
+    }
+}
+
+/// Reads a cell written as `column,row`.
+///
+/// # Arguments
+///
+/// * `text` - The cell as text.
+fn parse_cell(text: &str) -> Option<Cell> {
+    let (column, row) = text.split_once(',')?;
+    Some((column.parse().ok()?, row.parse().ok()?))
+}
+
+/// A snake as a snapshot records it.
+#[derive(Clone, Debug, PartialEq)]
+struct SnakeSnapshot {
+    cells: Vec<Cell>,      // Cells of the segments, head first
+    direction: Direction,  // Heading of the head
+    queue: Vec<Direction>, // Direction changes waiting for a movement step
+    score: u32,            // Points earned so far
+}
+
+/// The state of a run before one of its ticks, enough to play the run on from there.
+///
+/// Obstacles are left out, as they are rebuilt from the run's seed or level.
+#[derive(Clone, Debug, PartialEq)]
+struct Snapshot {
+    tick: i32,                  // Tick the run is about to play
+    draws: u64,                 // Number of words drawn from the run's RNG so far
+    wall_mode: WallMode,        // Wall mode, which can be switched during a run
+    auto_turn: bool,            // Whether the auto-turn at walls assist is on
+    snakes: Vec<SnakeSnapshot>, // Snakes of the players, player one first
+    fruit: Option<(Cell, i32)>, // Cell and age of the fruit, if one is on the board
+}
+
+impl Snapshot {
+    /// Writes the snapshot as text, one line for itself and one per snake, queue and fruit.
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "snapshot {} {} {} {}\n",
+            self.tick,
+            self.draws,
+            self.wall_mode.label().to_ascii_lowercase(),
+            self.auto_turn
+        );
+        for (index, snake) in self.snakes.iter().enumerate() {
+            text += &format!("snake {index} {} {}", snake.score, snake.direction);
+            for (column, row) in &snake.cells {
+                text += &format!(" {column},{row}");
+            }
+            text += "\n";
+            if !snake.queue.is_empty() {
+                text += &format!("queue {index}");
+                for direction in &snake.queue {
+                    text += &format!(" {direction}");
+                }
+                text += "\n";
+            }
+        }
+        if let Some(((column, row), age)) = self.fruit {
+            text += &format!("fruit {column} {row} {age}\n");
+        }
+        text
+    }
+}
+
+/// A replay being watched, fed into the game instead of the keyboard.
+struct Playback {
+    replay: Replay,         // The run being replayed
+    next: usize,            // Index of the next event to apply
+    desync: Option<String>, // Why the replay stopped matching the run, if it did
+}
+
+/// A value shown on the HUD, which the pause summary also spells out.
+///
+/// Both are built from the list returned by `GameState::hud_items`, so they always agree.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum HudItem {
+    Scores(u32, u32),                     // Scores of both players in a two-player run
+    Score { score: u32, best: u32 },      // Score of a solo run and the best one so far
+    Level { level: i32, remaining: i32 }, // Current level and fruits left until the next one
+    Difficulty(Difficulty),               // Difficulty of the run
+    Walls(WallMode, Option<KeyboardKey>), // Wall mode, and the key changing it if it can be
+    Bot,                                  // The bot steers player one
+    Replay,                               // A saved run is being watched
+    Clip,                                 // A saved clip is being watched
+}
+
+impl HudItem {
+    /// Returns the text of the item on the HUD.
+    fn label(self) -> String {
+        match self {
+            HudItem::Scores(first, second) => format!("P1: {first}   P2: {second}"),
+            HudItem::Score { score, best } => format!("SCORE: {score}   BEST: {best}"),
+            HudItem::Level { level, .. } => format!("LEVEL: {level}"),
+            HudItem::Difficulty(difficulty) => difficulty.label().to_string(),
+            HudItem::Walls(mode, _) => format!("WALLS: {}", mode.label()),
+            HudItem::Bot => "BOT".to_string(),
+            HudItem::Replay => "REPLAY".to_string(),
+            HudItem::Clip => "CLIP".to_string(),
+        }
+    }
+
+    /// Returns whether the item tells who is steering, which the HUD shows apart from the rest.
+    fn is_tag(self) -> bool {
+        matches!(self, HudItem::Bot | HudItem::Replay | HudItem::Clip)
+    }
+
+    /// Returns the line describing the item in the pause summary, if it has anything to tell.
+    fn describe(self) -> Option<String> {
+        let plural = |count: i64, noun: &str| {
+            if count == 1 {
+                format!("{count} {noun}")
+            } else {
+                format!("{count} {noun}S")
+            }
+        };
+
+        match self {
+            HudItem::Scores(first, second) => Some(match first.cmp(&second) {
+                Ordering::Greater => format!("PLAYER 1 LEADS {first} TO {second}"),
+                Ordering::Less => format!("PLAYER 2 LEADS {second} TO {first}"),
+                Ordering::Equal => format!("TIED AT {first}"),
+            }),
+            HudItem::Score { score, best } if score > best => {
+                Some(format!("{score} BEATS YOUR BEST OF {best}"))
+            }
+            HudItem::Score { score, best } if best > 0 => Some(format!(
+                "{} SHORT OF YOUR BEST OF {best}",
+                plural((best - score).into(), "POINT")
+            )),
+            HudItem::Score { .. } => None,
+            HudItem::Level { level, remaining } => Some(format!(
+                "LEVEL {level} - {} TO THE NEXT",
+                plural(remaining.into(), "FRUIT")
+            )),
+            HudItem::Difficulty(difficulty) => Some(format!("PLAYING ON {}", difficulty.label())),
+            HudItem::Walls(mode, Some(key)) => Some(format!(
+                "WALLS: {} - PRESS [{}] TO CHANGE",
+                mode.label(),
+                key_name(key)
+            )),
+            HudItem::Walls(mode, None) => Some(format!("WALLS: {}", mode.label())),
+            HudItem::Bot => Some("THE BOT IS STEERING".to_string()),
+            HudItem::Replay => Some("WATCHING A SAVED RUN".to_string()),
+            HudItem::Clip => Some("WATCHING A SAVED CLIP".to_string()),
+        }
+    }
+}
+
+/// Structure representing the snake, containing its position, size, direction, and color.
+#[derive(Clone, Copy)]
+struct Snake {
+    position: Cell,
+    size: Vector2,
+    direction: Direction,
+    color: Color,
+}
+
+/// Structure representing the food (fruit) in the game, containing its position, size, active state,
+/// color, and the number of ticks it has been waiting to be eaten.
+#[derive(Clone, Copy)]
+struct Food {
+    position: Cell,
+    size: Vector2,
+    active: bool,
+    color: Color,
+    age: i32,
+}
+
+/// Structure representing a player, containing their snake, buffered input, and score.
+struct Player {
+    snake: Vec<Snake>,                // Snake segments, head first
+    snake_position: Vec<Cell>,        // Segment positions before the last movement step
+    input_queue: VecDeque<Direction>, // Direction changes waiting for a movement step
+    score: u32,                       // Points earned in the current run
+    body_color: Color,                // Color of the segments behind the head
+}
+
+impl Player {
+    /// Creates a player whose snake is just its head.
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - The head segment of the snake.
+    /// * `body_color` - The color of the segments added behind the head.
+    fn new(head: Snake, body_color: Color) -> Self {
+        Player {
+            snake: vec![head],
+            snake_position: Vec::new(),
+            input_queue: VecDeque::with_capacity(INPUT_QUEUE_LENGTH),
+            score: 0,
+            body_color,
+        }
+    }
+}
+
+/// An error shown in the game window.
+struct Alert {
+    message: String,    // Text shown to the player
+    severity: Severity, // Whether the alert fades on its own or waits to be dismissed
+    remaining: f32,     // Seconds a toast stays on screen
+}
+
+/// The RNG of a run, counting the words drawn from it so a clip can pick the run up midway.
+///
+/// Every draw is made of 32-bit words taken in the order `StdRng` takes them itself, so the
+/// numbers drawn are the same as from a plain `StdRng` with the same seed.
+struct RunRng {
+    rng: StdRng, // Generator the words come from
+    draws: u64,  // Number of words drawn since the RNG was seeded
+}
+
+impl RunRng {
+    /// Creates the RNG of a run from its seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the run.
+    fn new(seed: u64) -> Self {
+        RunRng {
+            rng: StdRng::seed_from_u64(seed),
+            draws: 0,
+        }
+    }
+
+    /// Creates the RNG of a run as it was after a number of words were drawn from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the run.
+    /// * `draws` - The number of words already drawn.
+    fn resume(seed: u64, draws: u64) -> Self {
+        let mut rng = RunRng::new(seed);
+        for _ in 0..draws {
+            rng.next_u32();
+        }
+        rng
+    }
+}
+
+impl RngCore for RunRng {
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32();
+        let high = self.next_u32();
+        (u64::from(high) << 32) | u64::from(low)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let word = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Structure holding the entire game state, including the players, food, and relevant game variables.
+struct GameState {
+    tick_counter: i32,     // Number of simulation ticks run since the start of the run
+    phase: GamePhase,      // Current phase of the game
+    high_score: u32,       // Best score across all runs
+    save_high_score: bool, // Whether runs set the high score and run history kept on disk
+    bot: bool,             // Whether player one is steered by a bot or a script, not the keyboard
+    new_high_score: bool,  // Whether the last run beat the high score
+    hit_wall: bool,        // Whether player one's last run ended by hitting a wall
+    suggestion: Option<Difficulty>, // Difficulty suggested once after a new player's first runs
+    outcome: Option<Outcome>, // How the last two-player run ended
+    transform: WorldTransform, // Maps the cells of the board to the screen and back
+    square_size: i32,      // Size of each square of the grid, in pixels
+    columns: i32,          // Number of cells across the board
+    rows: i32,             // Number of cells down the board
+    players: Vec<Player>,  // Players of the current run, player one first
+    obstacles: HashSet<Cell>, // Wall blocks inside the board, kept across restarts
+    level_path: Option<PathBuf>, // Level file the obstacles were loaded from
+    fruit: Food,           // Represents the current fruit (food) in the game
+    puff: Option<(Cell, i32)>, // Cell the last fruit expired on, and the tick it did
+    config: GameConfig,    // Settings of the current run
+    seed: u64,             // Seed the RNG of the current run was created from
+    typed_seed: Option<u64>, // Seed of a random arena being typed on the title screen
+    rng: RunRng,           // Source of all randomness in a run
+    assist: bool,          // Enables the danger prediction assist
+    auto_turn: bool,       // Enables the auto-turn at walls assist
+    input_indicator: bool, // Shows the next buffered turn and the time to the next step
+    danger: [Option<(Cell, Color)>; DANGER_LOOKAHEAD], // Tinted cells ahead of player one's head
+    trace: Option<Trace>,  // Decisions of the last tick, recorded only for the trace overlay
+    recording: Replay,     // Inputs of the current run, saved on request once it ends
+    clip_snapshots: VecDeque<Snapshot>, // Snapshots a clip can start from, oldest first
+    clip_events: VecDeque<(i32, ReplayEvent)>, // Inputs since the oldest of those snapshots
+    replay_saved: Option<PathBuf>, // File the replay of the last run was saved to
+    clip_saved: Option<(PathBuf, i32)>, // File the last clip was saved to, and the tick it was saved on
+    playback: Option<Playback>,         // Replay driving the game instead of the keyboard, if any
+    volume: f32,                        // Volume of the music and sounds, from 0 to 1
+    volume_notice: f32,                 // Seconds the volume stays on screen
+    alerts: VecDeque<Alert>,            // Errors waiting to be shown, the one on screen first
+    theme: Theme,                       // Colors the game is drawn with
+    shape_cues: bool,                   // Tells the fruit and heads apart by shape, not just color
+    keys: KeyBindings,                  // Keys bound to each action
+    accumulator: f32,                   // Time rendered but not yet simulated, in seconds
+    pending_directions: [Vec<Direction>; 2], // Turns pressed since the last tick
+}
+
+impl GameState {
+    /// Creates a new game state with default initialization.
+    ///
+    /// The board is sized to fill the window the game starts in, and keeps that size when the
+    /// window is resized later on.
+    ///
+    /// # Arguments
+    ///
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    /// * `square_size` - The size of each square of the grid, in pixels.
+    fn new(screen_width: i32, screen_height: i32, square_size: i32) -> Self {
+        let board = (screen_width / square_size, screen_height / square_size);
+
+        GameState {
+            tick_counter: 0,
+            phase: GamePhase::Title,
+            high_score: 0,
+            save_high_score: true,
+            bot: false,
+            new_high_score: false,
+            hit_wall: false,
+            suggestion: None,
+            outcome: None,
+            transform: WorldTransform::centered(square_size, board, screen_width, screen_height),
+            square_size,
+            columns: board.0,
+            rows: board.1,
+            players: Vec::new(),
+            obstacles: HashSet::new(),
+            level_path: None,
+            fruit: Food {
+                position: (0, 0),
+                size: Vector2::new(square_size as f32, square_size as f32),
+                active: false,
+                color: THEMES[0].fruit,
+                age: 0,
+            },
+            puff: None,
+            config: GameConfig::new(Difficulty::Normal),
+            seed: 0,
+            typed_seed: None,
+            rng: RunRng::new(0),
+            assist: false,
+            auto_turn: false,
+            input_indicator: false,
+            danger: [None; DANGER_LOOKAHEAD],
+            trace: None,
+            recording: Replay::new(GameConfig::new(Difficulty::Normal), false, None, board),
+            clip_snapshots: VecDeque::new(),
+            clip_events: VecDeque::new(),
+            replay_saved: None,
+            clip_saved: None,
+            playback: None,
+            volume: 1.0,
+            volume_notice: 0.0,
+            alerts: VecDeque::new(),
+            theme: THEMES[0],
+            shape_cues: false,
+            keys: KeyBindings::default(),
+            accumulator: 0.0,
+            pending_directions: Default::default(),
+        }
+    }
+
+    /// Initializes or resets the game state, placing the snakes and fruit at their initial positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings to play the run with.
+    fn init_game(&mut self, config: GameConfig) {
+        self.config = config;
+        self.tick_counter = 0;
+        self.new_high_score = false;
+        self.hit_wall = false;
+        self.suggestion = None;
+        self.outcome = None;
+
+        // Seed the run so it can be replayed with the same inputs
+        self.seed = config.seed.unwrap_or_else(rand::random);
+        self.rng = RunRng::new(self.seed);
+
+        // Rewind a replay being watched, or start recording a new one
+        if let Some(playback) = &mut self.playback {
+            playback.next = 0;
+            playback.desync = None;
+            self.auto_turn = playback.replay.auto_turn;
+        }
+        self.recording = Replay::new(
+            GameConfig {
+                seed: Some(self.seed),
+                ..config
+            },
+            self.auto_turn,
+            self.level_path.clone(),
+            (self.columns, self.rows),
+        );
+        self.replay_saved = None;
+        self.clip_saved = None;
+
+        // Start every player with a single head segment, which is a different color from the body
+        let count = if config.two_player { 2 } else { 1 };
+        self.players = (0..count)
+            .map(|index| {
+                let (position, direction) = self.start(index);
+                let (head_color, body_color) = self.theme.snakes[index];
+                Player::new(
+                    Snake {
+                        position,
+                        size: Vector2::new(self.square_size as f32, self.square_size as f32),
+                        direction,
+                        color: head_color,
+                    },
+                    body_color,
+                )
+            })
+            .collect();
+
+        // Initialize fruit properties
+        self.fruit.size = Vector2::new(self.square_size as f32, self.square_size as f32);
+        self.fruit.color = self.theme.fruit;
+        self.fruit.active = false;
+        self.puff = None;
+
+        self.danger = [None; DANGER_LOOKAHEAD];
+        self.trace = config.trace_overlay.then(Trace::default);
+
+        // A random arena is rebuilt from each run's seed
+        if config.random_arena {
+            self.obstacles = self.generate_arena();
+        }
+
+        // A watched clip picks the run up where its snapshot was taken
+        if let Some(snapshot) = self
+            .playback
+            .as_ref()
+            .and_then(|playback| playback.replay.snapshot.clone())
+        {
+            self.restore_snapshot(&snapshot);
+        }
+        self.clip_snapshots.clear();
+        self.clip_events.clear();
+        self.take_snapshot();
+    }
+
+    /// Generates the obstacles of a random arena from the run's RNG.
+    ///
+    /// Walls are seeded at random and smoothed into clusters by a cellular automaton, then
+    /// mirrored through the center in two-player runs so neither side is favored. The spawn-safe
+    /// zone stays open, and any floor cut off from player one's start is joined back by carving
+    /// a path toward it, so every open cell can be reached.
+    fn generate_arena(&mut self) -> HashSet<Cell> {
+        let (columns, rows) = (self.columns, self.rows);
+        let symmetric = self.players.len() > 1;
+        let cells: Vec<Cell> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect();
+        let index = |column: i32, row: i32| (row * columns + column) as usize;
+        let mirror = |column: i32, row: i32| index(columns - 1 - column, rows - 1 - row);
+
+        let mut walls: Vec<bool> = cells
+            .iter()
+            .map(|_| self.rng.gen_bool(ARENA_WALL_DENSITY))
+            .collect();
+
+        // A wall survives with four wall neighbors and floor turns into wall with five
+        for _ in 0..ARENA_SMOOTHING_STEPS {
+            walls = cells
+                .iter()
+                .map(|&(column, row)| {
+                    let neighbors = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (column + dx, row + dy)))
+                        .filter(|&(x, y)| (x, y) != (column, row))
+                        .filter(|&(x, y)| x >= 0 && y >= 0 && x < columns && y < rows)
+                        .filter(|&(x, y)| walls[index(x, y)])
+                        .count();
+                    neighbors >= 5 || (neighbors == 4 && walls[index(column, row)])
+                })
+                .collect();
+        }
+
+        for &(column, row) in &cells {
+            if symmetric && index(column, row) < mirror(column, row) {
+                walls[mirror(column, row)] = walls[index(column, row)];
+            }
+            if self.is_in_spawn_safe_zone((column, row)) {
+                walls[index(column, row)] = false;
+            }
+        }
+
+        // Every carved path removes at least one wall, so this always ends
+        loop {
+            let mut reached = vec![false; walls.len()];
+            let mut pending = vec![(0, 0)];
+            reached[0] = true;
+            while let Some((column, row)) = pending.pop() {
+                for (x, y) in [
+                    (column + 1, row),
+                    (column - 1, row),
+                    (column, row + 1),
+                    (column, row - 1),
+                ] {
+                    if x >= 0 && y >= 0 && x < columns && y < rows {
+                        let i = index(x, y);
+                        if !walls[i] && !reached[i] {
+                            reached[i] = true;
+                            pending.push((x, y));
+                        }
+                    }
+                }
+            }
+
+            let Some(&(mut column, mut row)) = cells
+                .iter()
+                .find(|&&(column, row)| !walls[index(column, row)] && !reached[index(column, row)])
+            else {
+                break;
+            };
+
+            // Walk toward the start, first along the row then up the column
+            while !reached[index(column, row)] {
+                if column > 0 {
+                    column -= 1;
+                } else {
+                    row -= 1;
+                }
+                walls[index(column, row)] = false;
+                if symmetric {
+                    walls[mirror(column, row)] = false;
+                }
+            }
+        }
+
+        cells
+            .into_iter()
+            .filter(|&(column, row)| walls[index(column, row)])
+            .collect()
+    }
+
+    /// Recenters the board in a window of a new size, pausing a run in progress.
+    ///
+    /// The board keeps its number of cells, so nothing on it moves relative to the grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `screen_width` - The new width of the game window.
+    /// * `screen_height` - The new height of the game window.
+    fn resize(&mut self, screen_width: i32, screen_height: i32) {
+        let board = (self.columns, self.rows);
+        self.transform =
+            WorldTransform::centered(self.square_size, board, screen_width, screen_height);
+
+        // Give the player a moment to find the snake again
+        if self.phase == GamePhase::Playing {
+            self.set_phase(GamePhase::Paused);
+        }
+    }
+
+    /// Loads the obstacles of a level file, where `#` is a wall and `.` is floor, one row per line.
+    ///
+    /// Must be called after `init_game` so the walls line up with the grid. Fails if the file
+    /// holds any other character, does not fit on the board, or walls off a starting cell.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the level file.
+    fn load_level(&mut self, path: &Path) -> Result<(), SnakeError> {
+        let columns = self.columns as usize;
+        let rows = self.rows as usize;
+        let contents = fs::read_to_string(path)?;
+        let mut obstacles = HashSet::new();
+
+        for (row, line) in contents.lines().enumerate() {
+            let error = |msg: String| SnakeError::Parse {
+                file: path.to_path_buf(),
+                line: row + 1,
+                msg,
+            };
+            if row >= rows {
+                return Err(error(format!("more than {rows} rows")));
+            }
+            if line.chars().count() > columns {
+                return Err(error(format!("more than {columns} columns")));
+            }
+
+            for (column, tile) in line.chars().enumerate() {
+                match tile {
+                    '#' => {
+                        obstacles.insert((column as i32, row as i32));
+                    }
+                    '.' => {}
+                    _ => return Err(error(format!("unexpected '{tile}', expected '#' or '.'"))),
+                }
+            }
+        }
+
+        for index in 0..self.players.len() {
+            let (start, _) = self.start(index);
+            if obstacles.contains(&start) {
+                return Err(SnakeError::Validation(format!(
+                    "the starting cell of player {} is a wall",
+                    index + 1
+                )));
+            }
+        }
+
+        self.obstacles = obstacles;
+        self.level_path = Some(path.to_path_buf());
+        self.recording.level = self.level_path.clone();
+        Ok(())
+    }
+
+    /// Replaces the random arena shown on the title screen with one from a fresh seed.
+    ///
+    /// Any seed typed or given on the command line is dropped, so later runs get fresh arenas
+    /// too.
+    fn reroll_arena(&mut self) {
+        self.typed_seed = None;
+        self.init_game(GameConfig {
+            seed: None,
+            ..self.config
+        });
+    }
+
+    /// Adds a digit to the end of the seed being typed on the title screen, and shows the
+    /// random arena it generates.
+    ///
+    /// Digits that would overflow the seed are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `digit` - The digit typed, from 0 to 9.
+    fn type_seed_digit(&mut self, digit: u64) {
+        let typed = self.typed_seed.unwrap_or(0);
+        if let Some(seed) = typed
+            .checked_mul(10)
+            .and_then(|seed| seed.checked_add(digit))
+        {
+            self.typed_seed = Some(seed);
+            self.init_game(GameConfig {
+                seed: Some(seed),
+                ..self.config
+            });
+        }
+    }
+
+    /// Erases the last digit of the seed being typed on the title screen, and shows the random
+    /// arena of what is left. Erasing the last digit keeps the arena on screen.
+    fn erase_seed_digit(&mut self) {
+        let Some(typed) = self.typed_seed else {
+            return;
+        };
+
+        self.typed_seed = Some(typed / 10).filter(|&seed| seed > 0);
+        if let Some(seed) = self.typed_seed {
+            self.init_game(GameConfig {
+                seed: Some(seed),
+                ..self.config
+            });
+        }
+    }
+
+    /// Moves the game to another phase, running the exit hook of the current phase and then the
+    /// enter hook of the new one.
+    ///
+    /// # Arguments
+    ///
+    /// * `next` - The phase to move to.
+    fn set_phase(&mut self, next: GamePhase) {
+        debug_assert!(
+            self.phase.can_transition_to(next),
+            "illegal phase transition from {:?} to {:?}",
+            self.phase,
+            next
+        );
+
+        self.on_exit(self.phase);
+        self.phase = next;
+        self.on_enter(next);
+    }
+
+    /// Runs the side effects of leaving a phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - The phase being left.
+    fn on_exit(&mut self, phase: GamePhase) {
+        match phase {
+            // Time and turns left over when a run stops must not play out once it goes on
+            GamePhase::Playing => {
+                self.accumulator = 0.0;
+                self.pending_directions = Default::default();
+            }
+            GamePhase::Title | GamePhase::Paused | GamePhase::GameOver | GamePhase::Won => {}
+        }
+    }
+
+    /// Runs the side effects of entering a phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - The phase being entered.
+    fn on_enter(&mut self, phase: GamePhase) {
+        match phase {
+            GamePhase::GameOver | GamePhase::Won => {
+                // The preview would point past the end of the run
+                self.danger = [None; DANGER_LOOKAHEAD];
+
+                if let Some(playback) = &mut self.playback {
+                    if playback.desync.is_none() && playback.next < playback.replay.events.len() {
+                        playback.desync = Some("the run ended before the replay did".to_string());
+                    }
+                }
+
+                // Two-player scores are not comparable with solo runs, a replay was scored when
+                // it was recorded, and nobody earned the score of a bot
+                let score = self.players[0].score;
+                if !self.config.two_player
+                    && self.playback.is_none()
+                    && self.save_high_score
+                    && score > self.high_score
+                {
+                    // Another instance may have stored a better score in the meantime
+                    match save_high_score(score) {
+                        Ok(stored) => {
+                            self.high_score = stored;
+                            self.new_high_score = stored == score;
+                        }
+                        Err(err) => {
+                            self.high_score = score;
+                            self.new_high_score = true;
+                            self.report("Failed to save high score", err);
+                        }
+                    }
+                }
+
+                if self.counts_as_first_run() {
+                    self.record_first_run();
+                }
+            }
+            // Stale inputs must not fire on resume, and a replay drops them on its own
+            GamePhase::Paused if self.playback.is_none() => {
+                self.record_event(ReplayEvent::Pause);
+                for player in &mut self.players {
+                    player.input_queue.clear();
+                }
+            }
+            GamePhase::Paused => {}
+            GamePhase::Title | GamePhase::Playing => {}
+        }
+    }
+
+    /// Updates the game for each frame, reading the keyboard and running as many ticks as the
+    /// time since the last frame calls for.
+    ///
+    /// Pausing, restarting and the toggles respond on the frame they are pressed, while
+    /// directions wait for the next tick. After a long frame at most `MAX_CATCH_UP_TICKS` ticks
+    /// are run and the rest of the backlog is dropped, so the game slows down instead of
+    /// skipping ahead.
+    ///
+    /// # Arguments
+    ///
+    /// * `rl` - The `RaylibHandle` used for input and rendering.
+    /// * `thread` - The `RaylibThread` required by the `raylib` API.
+    /// * `controller` - The controller steering player one.
+    fn update_game(
+        &mut self,
+        rl: &mut RaylibHandle,
+        _thread: &RaylibThread,
+        controller: &mut dyn Controller,
+    ) {
+        let input = InputState::read(rl, &self.keys);
+
+        // A dialog holds the game until it is dismissed, while toasts fade on their own
+        if let Some(alert) = self.alerts.front_mut() {
+            match alert.severity {
+                Severity::Dialog => {
+                    if input.restart {
+                        self.alerts.pop_front();
+                    }
+                    return;
+                }
+                Severity::Toast => {
+                    alert.remaining -= rl.get_frame_time();
+                    if alert.remaining <= 0.0 {
+                        self.alerts.pop_front();
+                    }
+                }
+            }
+        }
+
+        self.handle_input(&input);
+        self.volume_notice = (self.volume_notice - rl.get_frame_time()).max(0.0);
+
+        if self.phase != GamePhase::Playing {
+            return;
+        }
+
+        self.buffer_turns(&input.directions);
+
+        self.accumulator += rl.get_frame_time();
+        let mut ticks = 0;
+        while self.accumulator >= TICK_DURATION && ticks < MAX_CATCH_UP_TICKS {
+            let mut input = InputState {
+                directions: mem::take(&mut self.pending_directions),
+                ..InputState::default()
+            };
+            input.directions[0] = controller.decide(self, &input);
+            // Taken out first, as a tick ending the run starts the next one from no time at all
+            self.accumulator -= TICK_DURATION;
+            self.tick(input);
+            ticks += 1;
+        }
+        self.accumulator = self.accumulator.min(TICK_DURATION);
+    }
+
+    /// Keeps the turns pressed on a frame for the next tick, after those of earlier frames that
+    /// have not had a tick yet, so quick presses between two ticks all count.
+    ///
+    /// # Arguments
+    ///
+    /// * `directions` - The directions each player pressed on the frame.
+    fn buffer_turns(&mut self, directions: &[Vec<Direction>; 2]) {
+        for ((pending, pressed), player) in self
+            .pending_directions
+            .iter_mut()
+            .zip(directions)
+            .zip(&self.players)
+        {
+            let heading = pending
+                .last()
+                .or(player.input_queue.back())
+                .copied()
+                .unwrap_or(player.snake[0].direction);
+            pending.extend(resolve_chord(heading, pressed));
+        }
+    }
+
+    /// Reacts to the keys that change the phase or the settings of the game.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The inputs pressed this frame.
+    fn handle_input(&mut self, input: &InputState) {
+        // Change the volume if '+' or '-' is pressed, whatever the phase
+        if input.volume_up || input.volume_down {
+            let step = if input.volume_up {
+                VOLUME_STEP
+            } else {
+                -VOLUME_STEP
+            };
+            self.volume = (self.volume + step).clamp(0.0, 1.0);
+            self.volume_notice = VOLUME_NOTICE_DURATION;
+        }
+
+        // Switch to the next color theme if 'L' is pressed, whatever the phase
+        if input.cycle_theme {
+            self.set_theme(self.theme.next());
+            let value = format!("\"{}\"", self.theme.name);
+            if let Err(err) = save_setting("theme", &value) {
+                self.report("Failed to save the theme", err);
+            }
+        }
+
+        // Toggle the shape cues if 'G' is pressed, whatever the phase
+        if input.toggle_shape_cues {
+            self.shape_cues = !self.shape_cues;
+            if let Err(err) = save_setting("shape_cues", &self.shape_cues.to_string()) {
+                self.report("Failed to save the shape cues", err);
+            }
+        }
+
+        match self.phase {
+            GamePhase::Title => {
+                if self.config.random_arena {
+                    if input.reroll_arena {
+                        self.reroll_arena();
+                    }
+                    if let Some(digit) = input.seed_digit {
+                        self.type_seed_digit(digit);
+                    }
+                    if input.erase_seed_digit {
+                        self.erase_seed_digit();
+                    }
+                }
+                if input.restart {
+                    self.set_phase(GamePhase::Playing);
+                }
+            }
+            GamePhase::Playing | GamePhase::Paused => self.update_running(input),
+            GamePhase::GameOver | GamePhase::Won => {
+                if input.save_replay && self.playback.is_none() && self.replay_saved.is_none() {
+                    match save_replay(&self.recording, "replay") {
+                        Ok(path) => self.replay_saved = Some(path),
+                        Err(err) => self.report("Failed to save replay", err),
+                    }
+                }
+
+                // Switch to the suggested difficulty from the next run on if 'Y' is pressed
+                if let Some(difficulty) = self.suggestion.filter(|_| input.accept_suggestion) {
+                    self.suggestion = None;
+                    self.config = GameConfig {
+                        two_player: self.config.two_player,
+                        seed: self.config.seed,
+                        trace_overlay: self.config.trace_overlay,
+                        random_arena: self.config.random_arena,
+                        fruit_lifetime: self.config.fruit_lifetime,
+                        ..GameConfig::new(difficulty)
+                    };
+                    let value = format!("\"{}\"", difficulty.label().to_ascii_lowercase());
+                    if let Err(err) = save_setting("difficulty", &value) {
+                        self.report("Failed to save the difficulty", err);
+                    }
+                }
+
+                if input.restart {
+                    // A replay restarts with the settings it was recorded with
+                    let config = self
+                        .playback
+                        .as_ref()
+                        .map_or(self.config, |playback| playback.replay.config);
+                    self.set_phase(GamePhase::Playing);
+                    self.init_game(config);
+                    self.skip_to_clip();
+                }
+            }
+        }
+    }
+
+    /// Draws the game with another theme from now on, recoloring the snakes and fruit in play.
+    ///
+    /// # Arguments
+    ///
+    /// * `theme` - The theme to use.
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.fruit.color = theme.fruit;
+        for (player, (head_color, body_color)) in self.players.iter_mut().zip(theme.snakes) {
+            player.body_color = body_color;
+            for (i, segment) in player.snake.iter_mut().enumerate() {
+                segment.color = if i == 0 { head_color } else { body_color };
+            }
+        }
+    }
+
+    /// Reports an error on the terminal and in the game window, as a toast or a dialog depending
+    /// on its severity.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - What the game was doing when the error happened.
+    /// * `err` - The error to report.
+    fn report(&mut self, context: &str, err: SnakeError) {
+        let message = format!("{context}: {err}");
+        eprintln!("{message}");
+        self.alerts.push_back(Alert {
+            message,
+            severity: err.severity(),
+            remaining: TOAST_DURATION,
+        });
+    }
+
+    /// Returns whether the run that just ended counts toward a new player's first runs.
+    ///
+    /// Only solo runs played at the keyboard count: a run steered by a bot or a script, or a
+    /// replay, tells nothing about how the player does.
+    fn counts_as_first_run(&self) -> bool {
+        !self.config.two_player && self.playback.is_none() && !self.bot && self.save_high_score
+    }
+
+    /// Records a finished run while the player is new, and suggests a difficulty once their first
+    /// runs are in.
+    ///
+    /// The suggestion is only made once: the history is marked done whether or not a rule
+    /// matched, and whether or not the player takes it.
+    fn record_first_run(&mut self) {
+        let Some(path) = data_path("first-runs") else {
+            return;
+        };
+
+        let run = RunSummary {
+            seconds: self.tick_counter as f32 * TICK_DURATION,
+            length: self.players[0].snake.len(),
+            hit_wall: self.hit_wall,
+        };
+        match save_first_run(&path, run) {
+            Ok(Some(runs)) => self.offer_suggestion(&runs),
+            Ok(None) => {}
+            Err(err) => self.report("Failed to save run history", err),
+        }
+    }
+
+    /// Suggests a difficulty from a new player's first runs, unless it is the one they play.
+    ///
+    /// # Arguments
+    ///
+    /// * `runs` - The first runs of the player.
+    fn offer_suggestion(&mut self, runs: &[RunSummary]) {
+        self.suggestion =
+            suggest_difficulty(runs).filter(|&difficulty| difficulty != self.config.difficulty);
+    }
+
+    /// Reacts to the keys that control a run in progress, either playing or paused.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The inputs pressed this frame.
+    fn update_running(&mut self, input: &InputState) {
+        // Toggle pause state if 'P' is pressed
+        if input.pause {
+            self.set_phase(if self.phase == GamePhase::Paused {
+                GamePhase::Playing
+            } else {
+                GamePhase::Paused
+            });
+        }
+
+        // Toggle the danger prediction assist if 'H' is pressed
+        if input.toggle_assist {
+            self.assist = !self.assist;
+        }
+
+        // Toggle the input timing indicator if 'I' is pressed
+        if input.toggle_indicator {
+            self.input_indicator = !self.input_indicator;
+        }
+
+        // Toggle the auto-turn at walls assist if 'T' is pressed
+        if input.toggle_auto_turn && self.playback.is_none() {
+            self.auto_turn = !self.auto_turn;
+            self.record_event(ReplayEvent::ToggleAutoTurn);
+        }
+
+        // Switch between solid and wrapping walls if 'M' is pressed while paused
+        if self.phase == GamePhase::Paused && input.toggle_walls && self.playback.is_none() {
+            self.config.wall_mode = self.config.wall_mode.toggled();
+            self.record_event(ReplayEvent::ToggleWalls);
+        }
+
+        // Save the last seconds of the run as a clip if 'C' is pressed
+        if input.save_clip && self.playback.is_none() {
+            self.save_clip();
+        }
+    }
+
+    /// Records an input of the run in progress, both for its replay and for clips.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The input, applied on the current tick.
+    fn record_event(&mut self, event: ReplayEvent) {
+        self.recording.events.push((self.tick_counter, event));
+        self.clip_events.push_back((self.tick_counter, event));
+    }
+
+    /// Returns the state of the run before the current tick.
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tick: self.tick_counter,
+            draws: self.rng.draws,
+            wall_mode: self.config.wall_mode,
+            auto_turn: self.auto_turn,
+            snakes: self
+                .players
+                .iter()
+                .map(|player| SnakeSnapshot {
+                    cells: player
+                        .snake
+                        .iter()
+                        .map(|segment| segment.position)
+                        .collect(),
+                    direction: player.snake[0].direction,
+                    queue: player.input_queue.iter().copied().collect(),
+                    score: player.score,
+                })
+                .collect(),
+            fruit: self
+                .fruit
+                .active
+                .then_some((self.fruit.position, self.fruit.age)),
+        }
+    }
+
+    /// Keeps a snapshot of the run for clips, and drops the snapshots and inputs no clip can
+    /// start from anymore.
+    ///
+    /// Only the latest snapshot taken before the last `CLIP_LENGTH` seconds is kept from that
+    /// far back, so the memory held stays the same however long the run lasts.
+    fn take_snapshot(&mut self) {
+        self.clip_snapshots.push_back(self.snapshot());
+
+        let start = self.tick_counter - clip_ticks();
+        while self
+            .clip_snapshots
+            .get(1)
+            .is_some_and(|snapshot| snapshot.tick <= start)
+        {
+            self.clip_snapshots.pop_front();
+        }
+        let oldest = self.clip_snapshots[0].tick;
+        while self
+            .clip_events
+            .front()
+            .is_some_and(|&(tick, _)| tick < oldest)
+        {
+            self.clip_events.pop_front();
+        }
+    }
+
+    /// Puts the run back in the state a snapshot recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The snapshot to play on from.
+    fn restore_snapshot(&mut self, snapshot: &Snapshot) {
+        self.tick_counter = snapshot.tick;
+        self.rng = RunRng::resume(self.seed, snapshot.draws);
+        self.config.wall_mode = snapshot.wall_mode;
+        self.auto_turn = snapshot.auto_turn;
+
+        for (player, saved) in self.players.iter_mut().zip(&snapshot.snakes) {
+            let head = player.snake[0];
+            player.snake = saved
+                .cells
+                .iter()
+                .enumerate()
+                .map(|(index, &position)| Snake {
+                    position,
+                    direction: saved.direction,
+                    color: if index == 0 {
+                        head.color
+                    } else {
+                        player.body_color
+                    },
+                    ..head
+                })
+                .collect();
+            player.input_queue = saved.queue.iter().copied().collect();
+            player.score = saved.score;
+        }
+
+        self.fruit.active = snapshot.fruit.is_some();
+        if let Some((position, age)) = snapshot.fruit {
+            self.fruit.position = position;
+            self.fruit.age = age;
+        }
+    }
+
+    /// Returns the last `CLIP_LENGTH` seconds of the run in progress as a clip.
+    ///
+    /// The clip starts from the latest snapshot taken before those seconds, and watching it
+    /// simulates the ticks up to them without showing them. Ticks spent paused are never part
+    /// of it.
+    fn clip(&self) -> Replay {
+        let end = self.tick_counter;
+        let start = (end - clip_ticks()).max(0);
+        let snapshot = self
+            .clip_snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.tick <= start)
+            .last()
+            .cloned();
+        let from = snapshot.as_ref().map_or(0, |snapshot| snapshot.tick);
+
+        Replay {
+            clip: Some((start, end)),
+            snapshot,
+            // Inputs on the last tick came after the clip was taken, like the pause before it
+            events: self
+                .clip_events
+                .iter()
+                .copied()
+                .filter(|&(tick, _)| tick >= from && tick < end)
+                .collect(),
+            ..Replay::new(
+                self.recording.config,
+                self.recording.auto_turn,
+                self.recording.level.clone(),
+                self.recording.board,
+            )
+        }
+    }
+
+    /// Saves the last `CLIP_LENGTH` seconds of the run in progress as a clip.
+    fn save_clip(&mut self) {
+        let clip = self.clip();
+        match save_replay(&clip, "clip") {
+            Ok(path) => self.clip_saved = Some((path, self.tick_counter)),
+            Err(err) => self.report("Failed to save clip", err),
+        }
+    }
+
+    /// Returns the tick a watched clip stops on, if a clip is being watched.
+    fn clip_end(&self) -> Option<i32> {
+        self.playback
+            .as_ref()
+            .and_then(|playback| playback.replay.clip)
+            .map(|(_, end)| end)
+    }
+
+    /// Simulates the ticks before the start of a watched clip, so it opens on its first tick.
+    ///
+    /// Must be called once the game is playing.
+    fn skip_to_clip(&mut self) {
+        let Some((start, _)) = self
+            .playback
+            .as_ref()
+            .and_then(|playback| playback.replay.clip)
+        else {
+            return;
+        };
+
+        while self.phase == GamePhase::Playing && self.tick_counter < start {
+            let tick = self.tick_counter;
+            self.tick(InputState::default());
+            // A clip out of sync stays frozen on the tick it failed
+            if self.tick_counter == tick {
+                break;
+            }
+        }
+    }
+
+    /// Advances a run in progress by one tick, including snake movement, fruit spawning, and
+    /// collision detection. Does nothing unless the game is playing.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The directions pressed since the last tick.
+    fn tick(&mut self, input: InputState) {
+        // A clip ends on the tick it was saved on
+        if self.phase == GamePhase::Playing
+            && self.clip_end().is_some_and(|end| self.tick_counter >= end)
+        {
+            self.set_phase(GamePhase::GameOver);
+        }
+
+        if self.phase == GamePhase::Playing {
+            if self.playback.is_some() {
+                // A replay that went out of sync stays frozen on the tick it failed
+                if !self.apply_replay_events() {
+                    return;
+                }
+            } else {
+                // Buffer snake direction changes based on user input
+                let players = self.players.len();
+                for (index, directions) in input.directions.into_iter().enumerate().take(players) {
+                    for direction in directions {
+                        if self.queue_direction(index, direction) {
+                            self.record_event(ReplayEvent::Turn(index, direction));
+                        }
+                    }
+                }
+            }
+
+            let moves = self.tick_counter % self.move_interval() == 0;
+            let mut dead = [false; MAX_PLAYERS];
+            for (index, dead) in dead.iter_mut().enumerate().take(self.players.len()) {
+                // Store the current positions of the snake
+                let player = &mut self.players[index];
+                player.snake_position.clear();
+                player
+                    .snake_position
+                    .extend(player.snake.iter().map(|segment| segment.position));
+
+                // Move the snake at specific tick intervals
+                if moves {
+                    // Apply at most one buffered direction change per step
+                    if let Some(direction) = self.players[index].input_queue.pop_front() {
+                        self.players[index].snake[0].direction = direction;
+                    } else if self.auto_turn {
+                        self.turn_away_from_wall(index);
+                    }
+
+                    let player = &mut self.players[index];
+                    for i in (1..player.snake.len()).rev() {
+                        player.snake[i].position = player.snake_position[i - 1];
+                    }
+                    let head = player.snake[0];
+                    player.snake[0].position = head.direction.advance(head.position, 1);
+                }
+
+                // Check for wall collisions, or wrap the head around to the opposite edge
+                let head = self.players[index].snake[0].position;
+                if self.is_out_of_bounds(head) {
+                    match self.config.wall_mode {
+                        WallMode::Solid => {
+                            *dead = true;
+                            self.hit_wall |= index == 0;
+                        }
+                        WallMode::Wrap => {
+                            self.players[index].snake[0].position = self.wrap_position(head)
+                        }
+                    }
+                }
+            }
+
+            // Check for collisions with obstacles and any body, then between the heads themselves
+            for (index, player) in self.players.iter().enumerate() {
+                let head = player.snake[0].position;
+                if self.obstacles.contains(&head) {
+                    dead[index] = true;
+                }
+                if self.players.iter().any(|other| {
+                    other.snake[1..]
+                        .iter()
+                        .any(|segment| segment.position == head)
+                }) {
+                    dead[index] = true;
+                }
+                // Heads that swapped cells passed through each other, which counts as meeting
+                if self.players.iter().enumerate().any(|(other, rival)| {
+                    other != index
+                        && (rival.snake[0].position == head
+                            || (rival.snake_position[0] == head
+                                && rival.snake[0].position == player.snake_position[0]))
+                }) {
+                    dead[index] = true;
+                }
+            }
+
+            // Record every cell the heads were just compared against
+            if let Some(trace) = &mut self.trace {
+                trace.compared.clear();
+                trace.compared.extend(&self.obstacles);
+                trace.compared.extend(
+                    self.players
+                        .iter()
+                        .flat_map(|player| &player.snake[1..])
+                        .map(|segment| segment.position),
+                );
+                if self.players.len() > 1 {
+                    trace
+                        .compared
+                        .extend(self.players.iter().map(|player| player.snake[0].position));
+                }
+
+                // A head may follow a tail, its own included, into the cell it just left
+                trace.exempted.clear();
+                if moves {
+                    let heads = self.players.iter().map(|player| player.snake[0].position);
+                    trace
+                        .exempted
+                        .extend(heads.enumerate().filter_map(|(index, head)| {
+                            let vacated = self.players.iter().enumerate().any(|(other, player)| {
+                                (other != index || player.snake.len() > 1)
+                                    && player.snake_position.last() == Some(&head)
+                            });
+                            vacated.then_some(head)
+                        }));
+                }
+            }
+
+            if dead.iter().any(|&dead| dead) {
+                if self.config.two_player {
+                    self.outcome = Some(match dead.iter().position(|&dead| !dead) {
+                        Some(survivor) => Outcome::Winner(survivor),
+                        None => Outcome::Draw,
+                    });
+                }
+                self.set_phase(GamePhase::GameOver);
+            }
+
+            // Move a fruit left uneaten for too long, leaving a puff where it was, unless a head
+            // reaches it on its last tick and eats it below
+            let reached = self
+                .players
+                .iter()
+                .any(|player| player.snake[0].position == self.fruit.position);
+            if self.fruit.active
+                && !reached
+                && self
+                    .config
+                    .fruit_lifetime
+                    .is_some_and(|lifetime| self.fruit.age >= lifetime)
+            {
+                self.puff = Some((self.fruit.position, self.tick_counter));
+                self.fruit.active = false;
+            }
+
+            // Spawn fruit on a free cell if it's not active, or win if there is none left
+            if !self.fruit.active && self.phase == GamePhase::Playing && !self.spawn_fruit() {
+                // Nobody can win by filling a board they share
+                if self.config.two_player {
+                    self.outcome = Some(Outcome::Draw);
+                    self.set_phase(GamePhase::GameOver);
+                } else {
+                    self.set_phase(GamePhase::Won);
+                }
+            }
+
+            // Check for collisions between a snake's head and the fruit, which is shared
+            for index in 0..self.players.len() {
+                let head = self.players[index].snake[0];
+                if self.fruit.active && head.position == self.fruit.position {
+                    let fruit_score = self.fruit_score(index);
+                    let player = &mut self.players[index];
+                    player.score += fruit_score;
+                    player.snake.push(Snake {
+                        position: player.snake_position[player.snake.len() - 1],
+                        color: player.body_color,
+                        ..head
+                    });
+                    self.fruit.active = false;
+                }
+            }
+
+            if self.assist && self.phase == GamePhase::Playing {
+                self.predict_danger();
+            }
+
+            // The fruit only ages while the run is being played, so a pause freezes its timer
+            if self.fruit.active {
+                self.fruit.age += 1;
+            }
+            self.tick_counter += 1;
+
+            if self.tick_counter % CLIP_SNAPSHOT_TICKS == 0 {
+                self.take_snapshot();
+            }
+        }
+    }
+
+    /// Returns every cell of the board that is not covered by a snake or an obstacle.
+    fn free_cells(&self) -> Vec<Cell> {
+        (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .filter(|cell| !self.obstacles.contains(cell))
+            .filter(|cell| {
+                !self
+                    .players
+                    .iter()
+                    .flat_map(|player| &player.snake)
+                    .any(|segment| segment.position == *cell)
+            })
+            .collect()
+    }
+
+    /// Returns the free cells a head can get to through other free cells.
+    ///
+    /// Only the trace overlay uses it, to show the fruit candidates walled off from every snake.
+    fn reachable_cells(&self) -> HashSet<Cell> {
+        let free: HashSet<Cell> = self.free_cells().into_iter().collect();
+        let mut pending: Vec<Cell> = self
+            .players
+            .iter()
+            .map(|player| player.snake[0].position)
+            .collect();
+        let mut reached = HashSet::new();
+
+        while let Some(cell) = pending.pop() {
+            for direction in Direction::ALL {
+                let mut next = direction.advance(cell, 1);
+                if self.config.wall_mode == WallMode::Wrap {
+                    next = self.wrap_position(next);
+                }
+                if free.contains(&next) && reached.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Places the fruit on a cell picked uniformly among the free ones.
+    ///
+    /// The first fruit of a run also avoids the spawn-safe zone, and a fruit that just expired
+    /// avoids the cell it left, unless that would leave no cell to pick from. Returns `false` if
+    /// the snakes cover the whole board.
+    fn spawn_fruit(&mut self) -> bool {
+        let mut candidates = self.free_cells();
+        let mut in_zone = Vec::new();
+        let reachable = self.trace.is_some().then(|| self.reachable_cells());
+
+        if let Some((expired, _)) = self.puff.filter(|&(_, tick)| tick == self.tick_counter) {
+            if candidates.len() > 1 {
+                candidates.retain(|&cell| cell != expired);
+            }
+        }
+
+        // Keep the first fruit out of the snakes' opening paths
+        if self.players.iter().all(|player| player.snake.len() == 1) {
+            let (inside_zone, outside_zone): (Vec<Cell>, Vec<Cell>) = candidates
+                .iter()
+                .partition(|&&cell| self.is_in_spawn_safe_zone(cell));
+            if !outside_zone.is_empty() {
+                candidates = outside_zone;
+                in_zone = inside_zone;
+            }
+        }
+
+        if let Some(trace) = &mut self.trace {
+            trace.candidates.clone_from(&candidates);
+            trace.rejected.clear();
+            trace.rejected.extend(
+                self.obstacles
+                    .iter()
+                    .chain(
+                        self.players.iter().flat_map(|player| {
+                            player.snake.iter().map(|segment| &segment.position)
+                        }),
+                    )
+                    .map(|&cell| (cell, Rejection::Occupied)),
+            );
+            trace
+                .rejected
+                .extend(in_zone.iter().map(|&cell| (cell, Rejection::SafetyZone)));
+            trace.unreachable.clear();
+            if let Some(reachable) = &reachable {
+                trace
+                    .unreachable
+                    .extend(candidates.iter().filter(|&cell| !reachable.contains(cell)));
+            }
+        }
+
+        match candidates.choose(&mut self.rng) {
+            Some(&cell) => {
+                self.fruit.position = cell;
+                self.fruit.active = true;
+                self.fruit.age = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the current speed level, starting at 1 and going up every `FRUITS_PER_LEVEL`
+    /// fruits eaten by the longest snake.
+    fn level(&self) -> i32 {
+        let longest = self
+            .players
+            .iter()
+            .map(|player| player.snake.len())
+            .max()
+            .unwrap_or(1);
+        (longest as i32 - 1) / FRUITS_PER_LEVEL + 1
+    }
+
+    /// Returns the values shown on the HUD, in the order they are drawn.
+    fn hud_items(&self) -> Vec<HudItem> {
+        let mut items = Vec::new();
+
+        items.push(if self.config.two_player {
+            HudItem::Scores(self.players[0].score, self.players[1].score)
+        } else {
+            HudItem::Score {
+                score: self.players[0].score,
+                best: self.high_score,
+            }
+        });
+
+        let longest = self.players.iter().map(|player| player.snake.len()).max();
+        let eaten = longest.unwrap_or(1) as i32 - 1;
+        items.push(HudItem::Level {
+            level: self.level(),
+            remaining: self.level() * FRUITS_PER_LEVEL - eaten,
+        });
+        items.push(HudItem::Difficulty(self.config.difficulty));
+
+        // A replay toggles the walls on its own
+        let toggle_walls = Some(self.keys.toggle_walls).filter(|_| self.playback.is_none());
+        items.push(HudItem::Walls(self.config.wall_mode, toggle_walls));
+
+        if self.clip_end().is_some() {
+            items.push(HudItem::Clip);
+        } else if self.playback.is_some() {
+            items.push(HudItem::Replay);
+        } else if self.bot {
+            items.push(HudItem::Bot);
+        }
+        items
+    }
+
+    /// Returns the line of the HUD drawn in the top-left corner, and the tag telling who is
+    /// steering drawn in the top-right one, if anyone but the player is.
+    fn hud_text(&self) -> (String, Option<String>) {
+        let (tags, items): (Vec<HudItem>, Vec<HudItem>) =
+            self.hud_items().into_iter().partition(|item| item.is_tag());
+        let line = items
+            .into_iter()
+            .map(HudItem::label)
+            .collect::<Vec<_>>()
+            .join("   ");
+        (line, tags.into_iter().next().map(HudItem::label))
+    }
+
+    /// Returns the lines shown under the pause headline, describing where the run stands.
+    ///
+    /// Each line spells out an item of the HUD, followed by how to resume.
+    fn pause_summary(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .hud_items()
+            .into_iter()
+            .filter_map(HudItem::describe)
+            .collect();
+        lines.push(format!("PRESS [{}] TO RESUME", key_name(self.keys.pause)));
+        lines
+    }
+
+    /// Returns the number of ticks between two movement steps at the current level.
+    ///
+    /// The interval follows the snakes' lengths, so it resets with them in `init_game`.
+    fn move_interval(&self) -> i32 {
+        (self.config.start_move_interval - (self.level() - 1)).max(MIN_MOVE_INTERVAL)
+    }
+
+    /// Returns how much of the wait for the next movement step has passed, from just above 0
+    /// right after a step to 1.0 when the next tick moves the snakes.
+    fn move_progress(&self) -> f32 {
+        let interval = self.move_interval();
+        // The tick counter has already moved past the tick of the last step
+        let since_step = (self.tick_counter - 1).rem_euclid(interval);
+        (since_step + 1) as f32 / interval as f32
+    }
+
+    /// Returns the points a player is awarded for eating a fruit at their snake's current length.
+    ///
+    /// Each segment behind the head adds one point on top of the configured fruit score, so
+    /// longer snakes earn more per fruit.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the player eating the fruit.
+    fn fruit_score(&self, index: usize) -> u32 {
+        self.config.fruit_score + (self.players[index].snake.len() - 1) as u32
+    }
+
+    /// Applies the events a watched replay recorded for the current tick.
+    ///
+    /// Returns `false` if the replay is out of sync, either from before or because one of the
+    /// events could not happen.
+    fn apply_replay_events(&mut self) -> bool {
+        let Some(playback) = &self.playback else {
+            return true;
+        };
+        if playback.desync.is_some() {
+            return false;
+        }
+
+        let events: Vec<ReplayEvent> = playback.replay.events[playback.next..]
+            .iter()
+            .take_while(|&&(frame, _)| frame == self.tick_counter)
+            .map(|&(_, event)| event)
+            .collect();
+        let mut desync = None;
+
+        for &event in &events {
+            match event {
+                ReplayEvent::Turn(index, direction) => {
+                    if index >= self.players.len() || !self.queue_direction(index, direction) {
+                        desync = Some(format!(
+                            "player {} could not turn {direction} on tick {}",
+                            index + 1,
+                            self.tick_counter
+                        ));
+                        break;
+                    }
+                }
+                ReplayEvent::Pause => {
+                    for player in &mut self.players {
+                        player.input_queue.clear();
+                    }
+                }
+                ReplayEvent::ToggleWalls => {
+                    self.config.wall_mode = self.config.wall_mode.toggled();
+                }
+                ReplayEvent::ToggleAutoTurn => self.auto_turn = !self.auto_turn,
+            }
+        }
+
+        let Some(playback) = &mut self.playback else {
+            return true;
+        };
+        playback.next += events.len();
+        playback.desync = desync;
+        playback.desync.is_none()
+    }
+
+    /// Buffers a direction change to be applied on a later movement step.
+    ///
+    /// The change is dropped if the queue is full, or if it does not turn relative to the last
+    /// buffered direction (or the current heading when nothing is buffered), which rules out
+    /// reversing into the body. Returns whether the change was buffered.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the player steering.
+    /// * `direction` - The new direction.
+    fn queue_direction(&mut self, index: usize, direction: Direction) -> bool {
+        let player = &mut self.players[index];
+        if player.input_queue.len() >= INPUT_QUEUE_LENGTH {
+            return false;
+        }
+
+        let last = *player
+            .input_queue
+            .back()
+            .unwrap_or(&player.snake[0].direction);
+        let turns = direction.is_horizontal() != last.is_horizontal();
+        if turns {
+            player.input_queue.push_back(direction);
+        }
+        turns
+    }
+
+    /// Checks whether a cell lies outside the playable area.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to check.
+    fn is_out_of_bounds(&self, (column, row): Cell) -> bool {
+        column < 0 || row < 0 || column >= self.columns || row >= self.rows
+    }
+
+    /// Moves a cell that left the board back in from the opposite edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to wrap.
+    fn wrap_position(&self, (column, row): Cell) -> Cell {
+        (column.rem_euclid(self.columns), row.rem_euclid(self.rows))
+    }
+
+    /// Returns the starting cell and direction of a player.
+    ///
+    /// Player one starts in the top-left corner heading right, player two in the bottom-right
+    /// corner heading left.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the player.
+    fn start(&self, index: usize) -> (Cell, Direction) {
+        if index == 0 {
+            ((0, 0), Direction::Right)
+        } else {
+            ((self.columns - 1, self.rows - 1), Direction::Left)
+        }
+    }
+
+    /// Checks whether a position lies in the spawn-safe zone reserved at the start of a run.
+    ///
+    /// The zone covers each player's starting cell, the next `SPAWN_SAFE_LENGTH` cells along
+    /// their starting direction, and their orthogonal neighbors. Cells outside the board never
+    /// match, so the zone is clipped at the walls.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The cell to check.
+    fn is_in_spawn_safe_zone(&self, position: Cell) -> bool {
+        (0..self.players.len()).any(|index| {
+            let (start, direction) = self.start(index);
+            let side = direction.clockwise();
+
+            (0..=SPAWN_SAFE_LENGTH).any(|i| {
+                let cell = direction.advance(start, i);
+                position == cell
+                    || position == side.advance(cell, 1)
+                    || position == side.advance(cell, -1)
+            })
+        })
+    }
+
+    /// Checks whether a head would die by moving straight into `cell` after `ticks` moves.
+    ///
+    /// The last `ticks` segments of every tail will have vacated their cells by then, so only
+    /// the segments still in place count as obstacles.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell the head would reach, already wrapped in wrap mode.
+    /// * `ticks` - The number of moves needed to reach `cell`.
+    fn is_deadly(&self, cell: Cell, ticks: usize) -> bool {
+        if self.config.wall_mode == WallMode::Solid && self.is_out_of_bounds(cell) {
+            return true;
+        }
+        if self.obstacles.contains(&cell) {
+            return true;
+        }
+
+        self.players.iter().any(|player| {
+            let remaining = player.snake.len().saturating_sub(ticks);
+            player.snake[..remaining]
+                .iter()
+                .any(|segment| segment.position == cell)
+        })
+    }
+
+    /// Counts the cells a player's head can move through along `direction` before dying.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the player.
+    /// * `direction` - The direction to look along.
+    fn runway(&self, index: usize, direction: Direction) -> usize {
+        let max = self.columns.max(self.rows) as usize;
+        let head = self.players[index].snake[0].position;
+
+        (1..=max)
+            .take_while(|&ticks| {
+                let mut cell = direction.advance(head, ticks as i32);
+                if self.config.wall_mode == WallMode::Wrap {
+                    cell = self.wrap_position(cell);
+                }
+                !self.is_deadly(cell, ticks)
+            })
+            .count()
+    }
+
+    /// Turns a player's head toward the side with more free runway if its next move is deadly,
+    /// whether it would hit a wall, an obstacle or a body.
+    ///
+    /// Ties go to the clockwise turn. If both sides are blocked the heading is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the player.
+    fn turn_away_from_wall(&mut self, index: usize) {
+        let head = self.players[index].snake[0];
+        if self.runway(index, head.direction) > 0 {
+            return;
+        }
+
+        let clockwise = head.direction.clockwise();
+        let counter_clockwise = head.direction.counter_clockwise();
+        let clockwise_runway = self.runway(index, clockwise);
+        let counter_clockwise_runway = self.runway(index, counter_clockwise);
+
+        if clockwise_runway > 0 && clockwise_runway >= counter_clockwise_runway {
+            self.players[index].snake[0].direction = clockwise;
+        } else if counter_clockwise_runway > 0 {
+            self.players[index].snake[0].direction = counter_clockwise;
+        }
+    }
+
+    /// Recomputes the tint of the cells ahead of player one's head for the danger prediction
+    /// assist.
+    ///
+    /// A cell is red if moving into it kills the snake, yellow if it is safe but the next one
+    /// is not, and green otherwise. Cells past the first deadly one are left untinted.
+    fn predict_danger(&mut self) {
+        let head = self.players[0].snake[0];
+        let cells: Vec<Cell> = (1..=DANGER_LOOKAHEAD + 1)
+            .map(|ticks| {
+                let cell = head.direction.advance(head.position, ticks as i32);
+                match self.config.wall_mode {
+                    WallMode::Solid => cell,
+                    WallMode::Wrap => self.wrap_position(cell),
+                }
+            })
+            .collect();
+        let deadly: Vec<bool> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, &cell)| self.is_deadly(cell, i + 1))
+            .collect();
+
+        self.danger = [None; DANGER_LOOKAHEAD];
+        for i in 0..DANGER_LOOKAHEAD {
+            if deadly[i] {
+                self.danger[i] = Some((cells[i], Color::RED));
+                break;
+            }
+            let color = if deadly[i + 1] {
+                Color::YELLOW
+            } else {
+                Color::GREEN
+            };
+            self.danger[i] = Some((cells[i], color));
+        }
+    }
+
+    /// Draws the game elements, including the grid, snake, fruit, and game over screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    fn draw_game(&self, d: &mut RaylibDrawHandle, screen_width: i32, screen_height: i32) {
+        d.clear_background(self.theme.background);
+
+        if self.phase == GamePhase::Title {
+            self.draw_title(d, screen_width, screen_height);
+            self.draw_volume(d, screen_width, screen_height);
+            self.draw_alert(d, screen_width, screen_height);
+            return;
+        }
+
+        // Draw game elements if the game is not over
+        if matches!(self.phase, GamePhase::Playing | GamePhase::Paused) {
+            // Keep the grid in the background when the shapes carry the meaning
+            let grid = if self.shape_cues {
+                self.theme.grid.alpha(0.35)
+            } else {
+                self.theme.grid
+            };
+            let (columns, rows) = self.transform.visible_cell_range();
+            for column in columns.start..=columns.end {
+                d.draw_line_v(
+                    self.transform.cell_to_screen((column, rows.start)),
+                    self.transform.cell_to_screen((column, rows.end)),
+                    grid,
+                );
+            }
+            for row in rows.start..=rows.end {
+                d.draw_line_v(
+                    self.transform.cell_to_screen((columns.start, row)),
+                    self.transform.cell_to_screen((columns.end, row)),
+                    grid,
+                );
+            }
+
+            // Tint the cells ahead of the head when the assist is enabled
+            if self.assist {
+                for &(cell, color) in self.danger.iter().flatten() {
+                    d.draw_rectangle_v(
+                        self.transform.cell_to_screen(cell),
+                        self.transform.cell_extent(),
+                        color.alpha(0.4),
+                    );
+                }
+            }
+
+            // Draw the obstacles, snakes and fruit
+            for &obstacle in &self.obstacles {
+                d.draw_rectangle_v(
+                    self.transform.cell_to_screen(obstacle),
+                    self.transform.cell_extent(),
+                    self.theme.walls,
+                );
+            }
+
+            for player in &self.players {
+                for segment in &player.snake {
+                    d.draw_rectangle_v(
+                        self.transform.cell_to_screen(segment.position),
+                        segment.size,
+                        segment.color,
+                    );
+                }
+
+                // Frame the head so it stands out from the body without relying on color
+                if self.shape_cues {
+                    let head = &player.snake[0];
+                    d.draw_rectangle_lines_ex(
+                        self.transform.cell_rectangle(head.position),
+                        (head.size.x / 8.0).max(2.0),
+                        self.theme.background,
+                    );
+                }
+
+                self.draw_snake_eyes(d, &player.snake[0]);
+
+                if self.input_indicator {
+                    self.draw_input_indicator(d, player);
+                }
+            }
+
+            // Draw the fruit as a diamond with the shape cues, so it differs from the snakes' squares
+            if self.shape_cues {
+                d.draw_poly(
+                    self.transform.cell_center(self.fruit.position),
+                    4,
+                    self.fruit.size.x / 2.0,
+                    0.0,
+                    self.fruit.color,
+                );
+            } else {
+                d.draw_rectangle_v(
+                    self.transform.cell_to_screen(self.fruit.position),
+                    self.fruit.size,
+                    self.fruit.color,
+                );
+            }
+
+            self.draw_fruit_timer(d);
+
+            if let Some(trace) = &self.trace {
+                self.draw_trace(d, trace);
+            }
+
+            // Draw the HUD in the top-left corner, and who is steering in the top-right one
+            let (hud, tag) = self.hud_text();
+            d.draw_text(&hud, 10, 10, 20, self.theme.text);
+
+            if let Some(tag) = tag {
+                d.draw_text(
+                    &tag,
+                    screen_width - d.measure_text(&tag, 20) - 10,
+                    10,
+                    20,
+                    Color::MAROON,
+                );
+            }
+
+            if let Some((path, tick)) = &self.clip_saved {
+                if self.tick_counter - tick < CLIP_NOTICE_TICKS {
+                    let notice = format!("CLIP SAVED TO {}", path.display());
+                    d.draw_text(&notice, 10, 35, 20, self.theme.muted_text);
+                }
+            }
+
+            // Draw the game over message if the game is over
+            if self.phase == GamePhase::Paused {
+                d.draw_text(
+                    "GAME PAUSED",
+                    screen_width / 2 - d.measure_text("GAME PAUSED", 40) / 2,
+                    screen_height / 2 - 40,
+                    40,
+                    self.theme.muted_text,
+                );
+
+                let summary = self.pause_summary();
+                for (i, line) in summary.iter().enumerate() {
+                    d.draw_text(
+                        line,
+                        screen_width / 2 - d.measure_text(line, 20) / 2,
+                        screen_height / 2 + 20 + 30 * i as i32,
+                        20,
+                        self.theme.muted_text,
+                    );
+                }
+
+                let seed = format!("SEED: {}", self.seed);
+                d.draw_text(
+                    &seed,
+                    screen_width / 2 - d.measure_text(&seed, 20) / 2,
+                    screen_height / 2 + 20 + 30 * summary.len() as i32,
+                    20,
+                    self.theme.faint_text,
+                );
+            }
+        } else {
+            let headline = match self.outcome {
+                Some(Outcome::Winner(0)) => Some(("PLAYER 1 WINS!", self.theme.snakes[0].0)),
+                Some(Outcome::Winner(_)) => Some(("PLAYER 2 WINS!", self.theme.snakes[1].0)),
+                Some(Outcome::Draw) => Some(("DRAW!", self.theme.text)),
+                None if self.phase == GamePhase::Won => Some(("YOU WIN!", Color::DARKGREEN)),
+                None if self.clip_end() == Some(self.tick_counter) => {
+                    Some(("END OF CLIP", Color::MAROON))
+                }
+                None => None,
+            };
+            if let Some((text, color)) = headline {
+                d.draw_text(
+                    text,
+                    screen_width / 2 - d.measure_text(text, 40) / 2,
+                    screen_height / 2 - 185,
+                    40,
+                    color,
+                );
+            }
+
+            if self.new_high_score {
+                d.draw_text(
+                    "NEW HIGH SCORE!",
+                    screen_width / 2 - d.measure_text("NEW HIGH SCORE!", 20) / 2,
+                    screen_height / 2 - 135,
+                    20,
+                    Color::GOLD,
+                );
+            }
+
+            let final_score = if self.config.two_player {
+                format!(
+                    "P1: {}   P2: {}",
+                    self.players[0].score, self.players[1].score
+                )
+            } else {
+                format!("FINAL SCORE: {}", self.players[0].score)
+            };
+            d.draw_text(
+                &final_score,
+                screen_width / 2 - d.measure_text(&final_score, 30) / 2,
+                screen_height / 2 - 100,
+                30,
+                self.theme.text,
+            );
+            let again = format!("PRESS [{}] TO PLAY AGAIN", key_name(self.keys.restart));
+            d.draw_text(
+                &again,
+                screen_width / 2 - d.measure_text(&again, 20) / 2,
+                screen_height / 2 - 50,
+                20,
+                self.theme.muted_text,
+            );
+
+            // Show the seed so the run can be replayed with --seed
+            let seed = format!("SEED: {}", self.seed);
+            d.draw_text(
+                &seed,
+                screen_width / 2 - d.measure_text(&seed, 20) / 2,
+                screen_height / 2 - 20,
+                20,
+                self.theme.faint_text,
+            );
+
+            let replay = match &self.replay_saved {
+                _ if self.playback.is_some() => None,
+                Some(path) => Some(format!("REPLAY SAVED TO {}", path.display())),
+                None => Some(format!(
+                    "PRESS [{}] TO SAVE THE REPLAY",
+                    key_name(self.keys.save_replay)
+                )),
+            };
+            if let Some(replay) = replay {
+                d.draw_text(
+                    &replay,
+                    screen_width / 2 - d.measure_text(&replay, 20) / 2,
+                    screen_height / 2 + 10,
+                    20,
+                    self.theme.muted_text,
+                );
+            }
+
+            if let Some(difficulty) = self.suggestion {
+                let suggestion = format!(
+                    "TRY {} - PRESS [{}] TO MAKE IT THE DEFAULT",
+                    difficulty.label(),
+                    key_name(self.keys.accept_suggestion)
+                );
+                d.draw_text(
+                    &suggestion,
+                    screen_width / 2 - d.measure_text(&suggestion, 20) / 2,
+                    screen_height / 2 + 50,
+                    20,
+                    Color::DARKGREEN,
+                );
+            }
+        }
+
+        // Tell a replay apart from a live run going wrong
+        if let Some(reason) = self
+            .playback
+            .as_ref()
+            .and_then(|playback| playback.desync.as_deref())
+        {
+            d.draw_text(
+                "REPLAY OUT OF SYNC",
+                screen_width / 2 - d.measure_text("REPLAY OUT OF SYNC", 30) / 2,
+                screen_height - 90,
+                30,
+                Color::RED,
+            );
+            d.draw_text(
+                reason,
+                screen_width / 2 - d.measure_text(reason, 20) / 2,
+                screen_height - 50,
+                20,
+                Color::RED,
+            );
+        }
+
+        self.draw_volume(d, screen_width, screen_height);
+        self.draw_alert(d, screen_width, screen_height);
+    }
+
+    /// Draws the volume in the bottom-right corner for a moment after it changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    fn draw_volume(&self, d: &mut RaylibDrawHandle, screen_width: i32, screen_height: i32) {
+        if self.volume_notice <= 0.0 {
+            return;
+        }
+
+        let volume = format!("VOLUME: {}%", (self.volume * 100.0).round());
+        d.draw_text(
+            &volume,
+            screen_width - d.measure_text(&volume, 20) - 10,
+            screen_height - 30,
+            20,
+            self.theme.text,
+        );
+    }
+
+    /// Draws the input timing indicator around a player's head.
+    ///
+    /// A thin arc fills up as the next movement step approaches, and a small arrow points in
+    /// the direction of the next buffered turn, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `player` - The player whose head gets the indicator.
+    fn draw_input_indicator(&self, d: &mut RaylibDrawHandle, player: &Player) {
+        let head = &player.snake[0];
+        let center = self.transform.cell_center(head.position);
+        let radius = head.size.x * 0.75;
+
+        d.draw_ring(
+            center,
+            radius,
+            radius + 2.0,
+            -90.0,
+            -90.0 + 360.0 * self.move_progress(),
+            32,
+            self.theme.text.alpha(0.6),
+        );
+
+        // Vertices go counter-clockwise so raylib does not cull the triangle
+        if let Some(&next) = player.input_queue.front() {
+            let heading = next.vector();
+            let side = Vector2::new(-heading.y, heading.x);
+            let tip = center + heading * (radius + 8.0);
+            let base = center + heading * (radius + 2.0);
+            d.draw_triangle(tip, base - side * 4.0, base + side * 4.0, self.theme.text);
+        }
+    }
+
+    /// Draws the error at the front of the alert queue, as a line at the bottom of the window for
+    /// a toast or over the whole game for a dialog.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    fn draw_alert(&self, d: &mut RaylibDrawHandle, screen_width: i32, screen_height: i32) {
+        let Some(alert) = self.alerts.front() else {
+            return;
+        };
+
+        match alert.severity {
+            Severity::Toast => {
+                d.draw_text(&alert.message, 10, screen_height - 30, 20, Color::MAROON);
+            }
+            Severity::Dialog => {
+                d.draw_rectangle_v(
+                    Vector2::zero(),
+                    Vector2::new(screen_width as f32, screen_height as f32),
+                    self.theme.background.alpha(0.9),
+                );
+                d.draw_text(
+                    &alert.message,
+                    screen_width / 2 - d.measure_text(&alert.message, 20) / 2,
+                    screen_height / 2 - 20,
+                    20,
+                    Color::MAROON,
+                );
+                let dismiss = format!("PRESS [{}] TO DISMISS", key_name(self.keys.restart));
+                d.draw_text(
+                    &dismiss,
+                    screen_width / 2 - d.measure_text(&dismiss, 20) / 2,
+                    screen_height / 2 + 20,
+                    20,
+                    self.theme.muted_text,
+                );
+            }
+        }
+    }
+
+    /// Draws the ring around a fruit about to expire and the puff left by the last one that did.
+    ///
+    /// The ring only shows during the last `FRUIT_RING_SHARE` of the fruit's life and depletes
+    /// as it runs out.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    fn draw_fruit_timer(&self, d: &mut RaylibDrawHandle) {
+        let radius = self.square_size as f32 * 0.7;
+
+        if let Some(lifetime) = self.config.fruit_lifetime.filter(|_| self.fruit.active) {
+            let left = (lifetime - self.fruit.age) as f32 / lifetime as f32;
+            if left <= FRUIT_RING_SHARE {
+                d.draw_ring(
+                    self.transform.cell_center(self.fruit.position),
+                    radius,
+                    radius + 2.0,
+                    -90.0,
+                    -90.0 + 360.0 * left / FRUIT_RING_SHARE,
+                    32,
+                    self.fruit.color,
+                );
+            }
+        }
+
+        if let Some((cell, tick)) = self.puff {
+            let age = self.tick_counter - tick;
+            if age < FRUIT_PUFF_TICKS {
+                let spread = age as f32 / FRUIT_PUFF_TICKS as f32;
+                d.draw_circle_v(
+                    self.transform.cell_center(cell),
+                    radius * (0.5 + spread),
+                    self.fruit.color.alpha(0.5 * (1.0 - spread)),
+                );
+            }
+        }
+    }
+
+    /// Draws the trace overlay on top of the board.
+    ///
+    /// Candidate cells of the last fruit spawn get a faint outline, crossed out if no head can
+    /// reach them, and rejected ones an outline colored by the reason. Cells compared against
+    /// the heads in the last collision check are marked with a dot, and heads spared because a
+    /// tail just left their cell with a ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `trace` - The decisions to draw.
+    fn draw_trace(&self, d: &mut RaylibDrawHandle, trace: &Trace) {
+        for &cell in &trace.candidates {
+            d.draw_rectangle_lines_ex(
+                self.transform.cell_rectangle(cell),
+                1.0,
+                Color::SKYBLUE.alpha(0.5),
+            );
+        }
+        for &(cell, reason) in &trace.rejected {
+            d.draw_rectangle_lines_ex(self.transform.cell_rectangle(cell), 2.0, reason.color());
+        }
+        for &cell in &trace.unreachable {
+            let corner = self.transform.cell_to_screen(cell);
+            let extent = self.transform.cell_extent();
+            d.draw_line_v(corner, corner + extent, Color::PURPLE);
+            d.draw_line_v(
+                corner + Vector2::new(extent.x, 0.0),
+                corner + Vector2::new(0.0, extent.y),
+                Color::PURPLE,
+            );
+        }
+        for &cell in &trace.compared {
+            d.draw_circle_v(self.transform.cell_center(cell), 3.0, Color::MAGENTA);
+        }
+        for &cell in &trace.exempted {
+            d.draw_ring(
+                self.transform.cell_center(cell),
+                5.0,
+                7.0,
+                0.0,
+                360.0,
+                16,
+                Color::LIME,
+            );
+        }
+    }
+
+    /// Draws the title screen with the game name, the controls and the start prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    fn draw_title(&self, d: &mut RaylibDrawHandle, screen_width: i32, screen_height: i32) {
+        // Preview the random arena behind the title, so it can be rerolled before playing
+        if self.config.random_arena {
+            for &obstacle in &self.obstacles {
+                d.draw_rectangle_v(
+                    self.transform.cell_to_screen(obstacle),
+                    self.transform.cell_extent(),
+                    self.theme.walls.alpha(0.3),
+                );
+            }
+
+            let seed = match self.typed_seed {
+                Some(typed) => format!("ARENA SEED: {typed}_"),
+                None => format!("ARENA SEED: {}", self.seed),
+            };
+            let arena = format!(
+                "{seed}   [{}] REROLL   [0-9] TYPE A SEED",
+                key_name(self.keys.reroll_arena)
+            );
+            d.draw_text(
+                &arena,
+                screen_width / 2 - d.measure_text(&arena, 20) / 2,
+                screen_height / 2 + 112,
+                20,
+                self.theme.faint_text,
+            );
+        }
+
+        d.draw_text(
+            "SNAKE",
+            screen_width / 2 - d.measure_text("SNAKE", 80) / 2,
+            screen_height / 2 - 165,
+            80,
+            self.theme.snakes[0].0,
+        );
+
+        let details = format!(
+            "DIFFICULTY: {}   BEST: {}",
+            self.config.difficulty.label(),
+            self.high_score
+        );
+        d.draw_text(
+            &details,
+            screen_width / 2 - d.measure_text(&details, 20) / 2,
+            screen_height / 2 - 65,
+            20,
+            self.theme.text,
+        );
+
+        let keys = &self.keys;
+        let controls = [
+            if self.config.two_player {
+                format!(
+                    "{} PLAYER 1   {} PLAYER 2",
+                    keys.steer_label(0),
+                    keys.steer_label(1)
+                )
+            } else {
+                format!("{} MOVE", keys.steer_label(0))
+            },
+            format!(
+                "[{}] PAUSE   [{}] WALLS (WHILE PAUSED)   [+][-] VOLUME",
+                key_name(keys.pause),
+                key_name(keys.toggle_walls)
+            ),
+            format!(
+                "[{}] DANGER ASSIST   [{}] AUTO-TURN ASSIST   [{}] SHAPE CUES",
+                key_name(keys.toggle_assist),
+                key_name(keys.toggle_auto_turn),
+                key_name(keys.toggle_shape_cues)
+            ),
+            format!(
+                "[{}] INPUT TIMING INDICATOR   [{}] SAVE CLIP   [{}] THEME",
+                key_name(keys.toggle_indicator),
+                key_name(keys.save_clip),
+                key_name(keys.cycle_theme)
+            ),
+        ];
+        for (i, line) in controls.iter().enumerate() {
+            d.draw_text(
+                line,
+                screen_width / 2 - d.measure_text(line, 20) / 2,
+                screen_height / 2 - 5 + 30 * i as i32,
+                20,
+                self.theme.muted_text,
+            );
+        }
+
+        let start = format!("PRESS [{}] TO START", key_name(keys.restart));
+        d.draw_text(
+            &start,
+            screen_width / 2 - d.measure_text(&start, 20) / 2,
+            screen_height / 2 + 145,
+            20,
+            self.theme.text,
+        );
+    }
+
+    /// Draws the eyes on a snake's head, with the pupils glancing toward the fruit.
+    ///
+    /// The blink timer is driven by `tick_counter` so the animation only depends on game time.
+    ///
+    /// # Arguments
+    ///
+    /// * `d` - The `RaylibDrawHandle` used for rendering.
+    /// * `head` - The head segment to draw the eyes on.
+    fn draw_snake_eyes(&self, d: &mut RaylibDrawHandle, head: &Snake) {
+        let center = self.transform.cell_center(head.position);
+        let heading = head.direction.vector();
+        let side = Vector2::new(-heading.y, heading.x);
+        let scale = self.square_size as f32 / SQUARE_SIZE as f32;
+        let (eye_radius, pupil_radius) = (EYE_RADIUS * scale, PUPIL_RADIUS * scale);
+
+        // Place the eyes side by side, slightly toward the front of the head
+        let eyes = [
+            center + heading * (head.size.x * 0.15) + side * (head.size.y * 0.22),
+            center + heading * (head.size.x * 0.15) - side * (head.size.y * 0.22),
+        ];
+
+        if self.tick_counter % BLINK_INTERVAL < BLINK_DURATION {
+            for eye in eyes {
+                d.draw_line_ex(
+                    eye - side * eye_radius,
+                    eye + side * eye_radius,
+                    2.0,
+                    Color::BLACK,
+                );
+            }
+            return;
+        }
+
+        // Offset the pupils toward the fruit, clamped so they stay inside the eye whites
+        let look = if self.fruit.active {
+            let fruit_center = self.transform.cell_center(self.fruit.position);
+            (fruit_center - center).normalized()
+                * (PUPIL_OFFSET * scale).min(eye_radius - pupil_radius)
+        } else {
+            Vector2::zero()
+        };
+
+        for eye in eyes {
+            d.draw_circle_v(eye, eye_radius, Color::RAYWHITE);
+            d.draw_circle_v(eye + look, pupil_radius, Color::BLACK);
+        }
+    }
+}
+
+/// Background music and sounds, following the phase of the game.
+struct Soundtrack<'aud> {
+    music: Option<Music<'aud>>, // Track looping during a run
+    sting: Option<Sound<'aud>>, // Sound played when a run ends
+    phase: GamePhase,           // Phase of the game on the last update
+    volume: Option<f32>,        // Volume last applied, if any
+}
+
+impl<'aud> Soundtrack<'aud> {
+    /// Loads the music and sounds, leaving out any that cannot be loaded.
+    ///
+    /// The default track and the sting are skipped silently when their files are missing, as
+    /// they do not ship with the game. A track named in the config file is reported instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `audio` - The audio device to play on.
+    /// * `music` - The track named in the config file, if any.
+    fn load(audio: &'aud RaylibAudio, music: Option<&Path>) -> Self {
+        let music = match music {
+            Some(path) => Some(path),
+            None => Some(Path::new(DEFAULT_MUSIC_PATH)).filter(|path| path.exists()),
+        }
+        .and_then(|path| {
+            audio
+                .new_music(&path.to_string_lossy())
+                .map_err(|err| eprintln!("Failed to load music: {err}"))
+                .ok()
+        });
+        let sting = Some(Path::new(GAME_OVER_STING_PATH))
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                audio
+                    .new_sound(&path.to_string_lossy())
+                    .map_err(|err| eprintln!("Failed to load sound: {err}"))
+                    .ok()
+            });
+
+        Soundtrack {
+            music,
+            sting,
+            phase: GamePhase::Title,
+            volume: None,
+        }
+    }
+
+    /// Starts, pauses or stops the music as the phase of the game changes, and keeps it
+    /// streaming. Must be called every frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `game_state` - The game being played.
+    fn update(&mut self, game_state: &GameState) {
+        if self.volume != Some(game_state.volume) {
+            self.volume = Some(game_state.volume);
+            if let Some(music) = &mut self.music {
+                music.set_volume(game_state.volume);
+            }
+            if let Some(sting) = &mut self.sting {
+                sting.set_volume(game_state.volume);
+            }
+        }
+
+        let previous = self.phase;
+        self.phase = game_state.phase;
+
+        if let Some(music) = &mut self.music {
+            if self.phase != previous {
+                match self.phase {
+                    GamePhase::Playing if previous == GamePhase::Paused => music.resume_stream(),
+                    GamePhase::Playing => music.play_stream(),
+                    GamePhase::Paused => music.pause_stream(),
+                    GamePhase::GameOver | GamePhase::Won => music.stop_stream(),
+                    GamePhase::Title => {}
+                }
+            }
+            music.update_stream();
+        }
+
+        if self.phase != previous && matches!(self.phase, GamePhase::GameOver | GamePhase::Won) {
+            if let Some(sting) = &self.sting {
+                sting.play();
+            }
+        }
+    }
+}
+
+/// Returns the path of a file stored in the game's data directory.
+///
+/// The file lives in the user's data directory (`$XDG_DATA_HOME` or `~/.local/share`), falling
+/// back to the directory of the executable when neither is available.
+///
+/// # Arguments
+///
+/// * `name` - The name of the file.
+fn data_path(name: &str) -> Option<PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+    match data_dir {
+        Some(dir) => Some(dir.join("snake-raylib").join(name)),
+        None => env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(name))),
+    }
+}
+
+/// Returns the path of the file storing the high score.
+fn high_score_path() -> Option<PathBuf> {
+    data_path("highscore")
+}
+
+/// Returns the path of the optional configuration file.
+///
+/// The file lives in the user's config directory (`$XDG_CONFIG_HOME` or `~/.config`), falling
+/// back to the directory of the executable when neither is available.
+fn config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    match config_dir {
+        Some(dir) => Some(dir.join("snake-raylib").join("snake.toml")),
+        None => env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("snake.toml"))),
+    }
+}
+
+/// Loads the high score from disk.
+///
+/// A missing file means no run has been recorded yet and loads as a score of 0.
+fn load_high_score() -> Result<u32, SnakeError> {
+    let Some(path) = high_score_path() else {
+        return Ok(0);
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    contents
+        .trim()
+        .parse()
+        .map_err(|_| SnakeError::Corrupt(path))
+}
+
+/// Saves the high score to disk, creating its directory if needed.
+///
+/// The file is locked and read again right before writing, and the better of both scores is
+/// kept, so a game running at the same time cannot overwrite a higher score with a lower one.
+/// Returns the score that ended up stored.
+///
+/// # Arguments
+///
+/// * `score` - The high score to store.
+fn save_high_score(score: u32) -> Result<u32, SnakeError> {
+    let path = high_score_path()
+        .ok_or_else(|| SnakeError::Validation("no data directory found".to_string()))?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _lock = lock_data_file(&path)?;
+
+    // A corrupt file is overwritten, as it was before merging
+    let best = match load_high_score() {
+        Ok(stored) => stored.max(score),
+        Err(SnakeError::Corrupt(_)) => score,
+        Err(err) => return Err(err),
+    };
+
+    write_data_file(&path, &best.to_string())?;
+    Ok(best)
+}
+
+/// Takes an advisory lock on the lock file next to a data file, so games running at the same
+/// time take turns reading and rewriting it. The lock is released when the returned file is
+/// dropped.
+///
+/// # Arguments
+///
+/// * `path` - The data file to lock.
+fn lock_data_file(path: &Path) -> Result<fs::File, SnakeError> {
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Replaces the contents of a data file.
+///
+/// The new contents go to a temporary file first and are renamed over the old ones, so a reader
+/// never sees a half-written file.
+///
+/// # Arguments
+///
+/// * `path` - The data file to replace.
+/// * `contents` - The new contents of the file.
+fn write_data_file(path: &Path, contents: &str) -> Result<(), SnakeError> {
+    let temporary = path.with_extension(format!("tmp.{}", process::id()));
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Loads the runs recorded for the difficulty suggestion, and whether it has been made already.
+///
+/// Each line of the file holds the seconds, length and wall hit (0 or 1) of one run; a final
+/// `done` line marks the suggestion as made. A missing file means no run has been recorded yet.
+///
+/// # Arguments
+///
+/// * `path` - The file holding the runs.
+fn load_first_runs(path: &Path) -> Result<(Vec<RunSummary>, bool), SnakeError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), false)),
+        Err(err) => return Err(err.into()),
+    };
+
+    let corrupt = || SnakeError::Corrupt(path.to_path_buf());
+    let mut runs = Vec::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => {}
+            ["done"] => return Ok((runs, true)),
+            [seconds, length, hit_wall] => runs.push(RunSummary {
+                seconds: seconds.parse().map_err(|_| corrupt())?,
+                length: length.parse().map_err(|_| corrupt())?,
+                hit_wall: match *hit_wall {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(corrupt()),
+                },
+            }),
+            _ => return Err(corrupt()),
+        }
+    }
+    Ok((runs, false))
+}
+
+/// Adds a finished run to the runs recorded for the difficulty suggestion, creating their
+/// directory if needed.
+///
+/// The file is locked and read again right before writing, and the run is added to the runs
+/// stored there, so runs finished by a game running at the same time are kept. Once the
+/// suggestion has been made no more runs are added. Returns every recorded run when this one
+/// completed them, so exactly one game makes the suggestion.
+///
+/// # Arguments
+///
+/// * `path` - The file holding the runs.
+/// * `run` - The run that just ended.
+fn save_first_run(path: &Path, run: RunSummary) -> Result<Option<Vec<RunSummary>>, SnakeError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let _lock = lock_data_file(path)?;
+
+    // A corrupt file is overwritten, as the high score is
+    let mut runs = match load_first_runs(path) {
+        Ok((_, true)) => return Ok(None),
+        Ok((runs, false)) => runs,
+        Err(SnakeError::Corrupt(_)) => Vec::new(),
+        Err(err) => return Err(err),
+    };
+    runs.push(run);
+    let done = runs.len() >= SUGGESTION_RUNS;
+
+    let mut contents: String = runs
+        .iter()
+        .map(|run| {
+            format!(
+                "{} {} {}\n",
+                run.seconds,
+                run.length,
+                u8::from(run.hit_wall)
+            )
+        })
+        .collect();
+    if done {
+        contents.push_str("done\n");
+    }
+    write_data_file(path, &contents)?;
+    Ok(done.then_some(runs))
+}
+
+/// Returns the number of ticks in a clip.
+fn clip_ticks() -> i32 {
+    (CLIP_LENGTH / TICK_DURATION).round() as i32
+}
+
+/// Formats the current UTC time as `YYYYMMDD-HHMMSS`.
+fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // Convert the day count to a civil date, following Howard Hinnant's civil_from_days
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Saves a replay to a timestamped file in the working directory and returns its path.
+///
+/// # Arguments
+///
+/// * `replay` - The replay to save.
+/// * `prefix` - The start of the file name, such as `replay` or `clip`.
+fn save_replay(replay: &Replay, prefix: &str) -> Result<PathBuf, SnakeError> {
+    let path = PathBuf::from(format!("{prefix}-{}.txt", timestamp()));
+    fs::write(&path, replay.to_text())?;
+    Ok(path)
+}
+
+/// Loads a replay saved by `save_replay`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the replay file.
+fn load_replay(path: &Path) -> Result<Replay, SnakeError> {
+    Replay::parse(&fs::read_to_string(path)?, path)
+}
+
+/// Loads the input script of a headless run.
+///
+/// Each line holds a tick number and the direction pressed on that tick, such as `12 up`.
+/// Blank lines and lines starting with `#` are skipped.
+///
+/// # Arguments
+///
+/// * `path` - The path of the script file.
+fn load_script(path: &Path) -> Result<HashMap<u32, Direction>, SnakeError> {
+    let contents = fs::read_to_string(path)?;
+    let mut script = HashMap::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = |msg: String| SnakeError::Parse {
+            file: path.to_path_buf(),
+            line: number + 1,
+            msg,
+        };
+        let invalid = || error("expected a tick number and a direction".to_string());
+        let mut fields = line.split_whitespace();
+        let frame = fields
+            .next()
+            .and_then(|frame| frame.parse().ok())
+            .ok_or_else(invalid)?;
+        let direction = fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|err: SnakeError| error(err.to_string()))?;
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        script.insert(frame, direction);
+    }
+
+    Ok(script)
+}
+
+/// Simulates a run without opening a window and prints how it went.
+///
+/// The run stops after `max_ticks` ticks or when it ends.
+///
+/// # Arguments
+///
+/// * `game_state` - The game to simulate, already initialized.
+/// * `max_ticks` - The largest number of ticks to simulate.
+/// * `controller` - The controller steering player one.
+fn run_headless(game_state: &mut GameState, max_ticks: u32, controller: &mut dyn Controller) {
+    game_state.set_phase(GamePhase::Playing);
+    game_state.skip_to_clip();
+
+    let mut ticks = 0;
+    while ticks < max_ticks && game_state.phase == GamePhase::Playing {
+        let mut input = InputState::default();
+        input.directions[0] = controller.decide(game_state, &input);
+        game_state.tick(input);
+        ticks += 1;
+    }
+
+    let length = game_state.players[0].snake.len();
+    println!(
+        "ticks survived: {ticks}, fruits eaten: {}, final length: {length}",
+        length - 1
+    );
+}
+
+/// Values each numeric setting accepts, by its key in the configuration file.
+///
+/// The command-line options overriding these settings are held to the same ranges.
+const SETTING_RANGES: [(&str, RangeInclusive<i64>); 6] = [
+    ("width", 160..=7680),
+    ("height", 120..=4320),
+    ("cell_size", 8..=128),
+    ("fps", 10..=500),
+    ("volume", 0..=100),
+    ("fruit_lifetime", 0..=600),
+];
+
+/// Returns the values a numeric setting accepts, or every value if it has no range.
+///
+/// # Arguments
+///
+/// * `key` - The key of the setting in the configuration file.
+fn setting_range(key: &str) -> RangeInclusive<i64> {
+    SETTING_RANGES
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map_or(i64::MIN..=i64::MAX, |(_, range)| range.clone())
+}
+
+/// Settings read from the configuration file, which command-line options override.
+#[derive(Clone, Debug)]
+struct Settings {
+    difficulty: Difficulty, // Difficulty preset of the first run
+    screen_width: i32,      // Width of the window, in pixels
+    screen_height: i32,     // Height of the window, in pixels
+    square_size: i32,       // Size of each square of the grid, in pixels
+    fps: u32,               // Frame rate the window is capped at
+    volume: u32,            // Volume of the music and sounds, in percent
+    music: Option<PathBuf>, // Music looping during a run, instead of the default track
+    theme: Theme,           // Colors the game is drawn with
+    shape_cues: bool,       // Tells the fruit and heads apart by shape, not just color
+    fruit_lifetime: u32,    // Seconds a fruit waits to be eaten before moving, 0 if it never does
+    keys: KeyBindings,      // Keys bound to each action
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            difficulty: Difficulty::Normal,
+            screen_width: 800,
+            screen_height: 450,
+            square_size: SQUARE_SIZE,
+            fps: 60,
+            volume: 50,
+            music: None,
+            theme: THEMES[0],
+            shape_cues: false,
+            fruit_lifetime: 0,
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from the configuration file, using the defaults if there is none.
+    ///
+    /// A problem with the file never keeps the game from starting: a key that cannot be used
+    /// falls back to its default while the others still apply. The problems are returned along
+    /// with the settings, to be reported once the game starts.
+    fn load() -> (Self, Vec<SnakeError>) {
+        let Some(path) = config_path() else {
+            return (Settings::default(), Vec::new());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Settings::parse(&contents, &path),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (Settings::default(), Vec::new()),
+            Err(err) => (Settings::default(), vec![err.into()]),
+        }
+    }
+
+    /// Reads the settings from the contents of a configuration file, along with the problems
+    /// found in it.
+    ///
+    /// The file holds one `key = value` pair per line, as in TOML, with `#` starting a comment
+    /// outside of quotes. Key bindings go in a `[keys]` section after the other settings. A
+    /// number outside the range a key accepts is rejected, and the key keeps its default.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The contents of the configuration file.
+    /// * `path` - The path of the file, used in error messages.
+    fn parse(text: &str, path: &Path) -> (Self, Vec<SnakeError>) {
+        let defaults = Settings::default();
+        let mut settings = defaults.clone();
+        let mut problems = Vec::new();
+        let mut in_keys = false;
+        let mut cell_size_line = None;
+        let mut bound_lines = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |msg: String| SnakeError::Parse {
+                file: path.to_path_buf(),
+                line: number + 1,
+                msg,
+            };
+            if let Some(section) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                in_keys = section.trim() == "keys";
+                if !in_keys {
+                    problems.push(error(format!("unknown section '{section}'")));
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                problems.push(error("expected 'key = value'".to_string()));
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let quoted = || {
+                value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+            };
+
+            if in_keys {
+                let Some(bound) = settings.keys.key_mut(key) else {
+                    problems.push(error(format!("unknown action '{key}'")));
+                    continue;
+                };
+                match quoted().and_then(|name| {
+                    KEY_NAMES
+                        .iter()
+                        .find(|&&(named, _)| named.eq_ignore_ascii_case(name))
+                }) {
+                    Some(&(_, named)) => {
+                        *bound = named;
+                        if let Some(&action) = KeyBindings::ACTIONS.iter().find(|&&a| a == key) {
+                            bound_lines.retain(|&(other, _)| other != action);
+                            bound_lines.push((action, number + 1));
+                        }
+                    }
+                    None => problems.push(error(format!(
+                        "unknown key {value} for '{key}', using the default"
+                    ))),
+                }
+                continue;
+            }
+
+            // Numbers are checked against the range each key accepts
+            let ranged = || match value.parse::<i64>() {
+                Ok(number) if setting_range(key).contains(&number) => Ok(number),
+                Ok(_) => Err(format!(
+                    "'{key}' must be between {} and {}, using the default",
+                    setting_range(key).start(),
+                    setting_range(key).end()
+                )),
+                Err(_) => Err(format!(
+                    "invalid value {value} for '{key}', using the default"
+                )),
+            };
+            let invalid = || format!("invalid value {value} for '{key}', using the default");
+
+            let result = match key {
+                "difficulty" => quoted()
+                    .and_then(|value| value.parse().ok())
+                    .map(|value| settings.difficulty = value)
+                    .ok_or_else(invalid),
+                "width" => ranged().map(|value| settings.screen_width = value as i32),
+                "height" => ranged().map(|value| settings.screen_height = value as i32),
+                "cell_size" => ranged().map(|value| {
+                    settings.square_size = value as i32;
+                    cell_size_line = Some(number + 1);
+                }),
+                "fps" => ranged().map(|value| settings.fps = value as u32),
+                "volume" => ranged().map(|value| settings.volume = value as u32),
+                "music" => quoted()
+                    .map(|value| settings.music = Some(PathBuf::from(value)))
+                    .ok_or_else(invalid),
+                "theme" => quoted()
+                    .and_then(Theme::named)
+                    .map(|value| settings.theme = value)
+                    .ok_or_else(invalid),
+                "shape_cues" => value
+                    .parse()
+                    .map(|value| settings.shape_cues = value)
+                    .map_err(|_| invalid()),
+                "fruit_lifetime" => ranged().map(|value| settings.fruit_lifetime = value as u32),
+                _ => Err(format!("unknown key '{key}'")),
+            };
+            if let Err(msg) = result {
+                problems.push(error(msg));
+            }
+        }
+
+        // A key typing seeds cannot be bound, nor can two actions read on the same screen share
+        // one. The action bound in the file falls back to its default, which may in turn clash
+        // with another binding of the file
+        loop {
+            let rejected: Vec<_> = bound_lines
+                .iter()
+                .filter_map(|&(action, line)| {
+                    let key = settings.keys.key(action)?;
+                    let msg = if KeyBindings::RESERVED.contains(&key) {
+                        format!(
+                            "{} types seeds and cannot be bound to '{action}', using the default",
+                            key_name(key)
+                        )
+                    } else {
+                        let other = settings.keys.clash(action)?;
+                        format!(
+                            "'{action}' cannot share {} with '{other}', using the default",
+                            key_name(key)
+                        )
+                    };
+                    Some((action, line, msg))
+                })
+                .collect();
+            if rejected.is_empty() {
+                break;
+            }
+
+            for (action, line, msg) in rejected {
+                problems.push(SnakeError::Parse {
+                    file: path.to_path_buf(),
+                    line,
+                    msg,
+                });
+                if let (Some(key), Some(default)) =
+                    (settings.keys.key_mut(action), defaults.keys.key(action))
+                {
+                    *key = default;
+                }
+                bound_lines.retain(|&(other, _)| other != action);
+            }
+        }
+
+        // Keep at least two cells each way, as with the command-line options. Within their
+        // ranges, only a cell size set in the file can be too large for the window
+        if settings.square_size > settings.screen_width.min(settings.screen_height) / 2 {
+            problems.push(SnakeError::Parse {
+                file: path.to_path_buf(),
+                line: cell_size_line.unwrap_or_default(),
+                msg: "'cell_size' is too large for the window, using the default window and \
+                      cell size"
+                    .to_string(),
+            });
+            settings.screen_width = defaults.screen_width;
+            settings.screen_height = defaults.screen_height;
+            settings.square_size = defaults.square_size;
+        }
+
+        (settings, problems)
+    }
+
+    /// Returns a configuration file holding the default settings, each with a comment.
+    fn template() -> String {
+        let defaults = Settings::default();
+        format!(
+            "# Settings of snake-raylib. Command-line options take precedence over them.
+
+# Difficulty of the first run: \"easy\", \"normal\" or \"hard\"
+difficulty = \"{}\"
+
+# Size of the window, in pixels
+width = {}
+height = {}
+
+# Size of each square of the grid, in pixels
+cell_size = {}
+
+# Frame rate the window is capped at
+fps = {}
+
+# Volume of the music and sounds, from 0 to 100
+volume = {}
+
+# Music looping during a run, {DEFAULT_MUSIC_PATH} when left out
+# music = \"{DEFAULT_MUSIC_PATH}\"
+
+# Colors of the game: \"classic\", \"dark\" or \"high-contrast\"
+theme = \"{}\"
+
+# Draws the fruit as a diamond and frames the snake heads, so no color has to be told apart
+shape_cues = {}
+
+# Seconds a fruit waits to be eaten before it moves elsewhere, or 0 to keep it in place
+fruit_lifetime = {}
+
+# Keys bound to each action: a letter, \"UP\", \"DOWN\", \"LEFT\", \"RIGHT\", \"ENTER\",
+# \"SPACE\" or \"TAB\". Actions read on the same screen cannot share a key. The volume keys,
+# and the digits and Backspace typing a seed, are fixed.
+[keys]
+{}",
+            defaults.difficulty.label().to_ascii_lowercase(),
+            defaults.screen_width,
+            defaults.screen_height,
+            defaults.square_size,
+            defaults.fps,
+            defaults.volume,
+            defaults.theme.name,
+            defaults.shape_cues,
+            defaults.fruit_lifetime,
+            KeyBindings::ACTIONS
+                .iter()
+                .map(|&action| {
+                    let mut keys = defaults.keys;
+                    let key = keys.key_mut(action).map_or("?", |key| key_name(*key));
+                    format!("{action} = \"{key}\"\n")
+                })
+                .collect::<String>()
+        )
+    }
+}
+
+/// Returns a line of the configuration file without its comment, if it has one.
+///
+/// A `#` inside a quoted value, such as a music file name, does not start a comment.
+///
+/// # Arguments
+///
+/// * `line` - The line to strip.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Writes a configuration file holding the default settings, unless one already exists.
+///
+/// Returns the path the file was written to.
+fn write_default_config() -> Result<PathBuf, SnakeError> {
+    let path = config_path()
+        .ok_or_else(|| SnakeError::Validation("no config directory found".to_string()))?;
+    if path.exists() {
+        return Err(SnakeError::Validation(format!(
+            "{} already exists",
+            path.display()
+        )));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, Settings::template())?;
+    Ok(path)
+}
+
+/// Sets a key of the configuration file, keeping the rest of it as it is.
+///
+/// The file is created from the default template if it does not exist yet.
+///
+/// # Arguments
+///
+/// * `key` - The key to set.
+/// * `value` - The value to give it, written as is.
+fn save_setting(key: &str, value: &str) -> Result<(), SnakeError> {
+    let path = config_path()
+        .ok_or_else(|| SnakeError::Validation("no config directory found".to_string()))?;
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Settings::template(),
+        Err(err) => return Err(err.into()),
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, with_setting(&contents, key, value))?;
+    Ok(())
+}
+
+/// Returns the contents of a configuration file with a key set, keeping the rest as it is.
+///
+/// Only keys outside of any section are set. The key is added before the first section if the
+/// file does not set it yet, as keys after a section header belong to that section.
+///
+/// # Arguments
+///
+/// * `contents` - The contents of the configuration file.
+/// * `key` - The key to set.
+/// * `value` - The value to give it, written as is.
+fn with_setting(contents: &str, key: &str, value: &str) -> String {
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let first_section = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..first_section].iter().position(|line| {
+        !line.trim_start().starts_with('#')
+            && line
+                .split_once('=')
+                .is_some_and(|(name, _)| name.trim() == key)
+    });
+
+    match existing {
+        Some(index) => lines[index] = format!("{key} = {value}"),
+        None if first_section == lines.len() => lines.push(format!("{key} = {value}")),
+        None => {
+            // Keep the comment above the first section with it
+            let mut index = first_section;
+            while index > 0 && lines[index - 1].trim_start().starts_with('#') {
+                index -= 1;
+            }
+            lines.splice(index..index, [format!("{key} = {value}"), String::new()]);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Command-line arguments of the game.
+struct Args {
+    config: GameConfig,        // Configuration of the first run
+    level: Option<PathBuf>,    // Level file to load obstacles from
+    headless: Option<u32>,     // Number of ticks to simulate without a window
+    script: Option<PathBuf>,   // Input script driving a headless run
+    bot: bool,                 // Lets the greedy bot steer player one
+    replay: Option<PathBuf>,   // Replay file to watch instead of playing
+    screen_width: i32,         // Width of the window, in pixels
+    screen_height: i32,        // Height of the window, in pixels
+    square_size: i32,          // Size of each square of the grid, in pixels
+    fps: u32,                  // Frame rate the window is capped at
+    volume: f32,               // Volume of the music and sounds, from 0 to 1
+    music: Option<PathBuf>,    // Music looping during a run, instead of the default track
+    theme: Theme,              // Colors the game is drawn with
+    shape_cues: bool,          // Tells the fruit and heads apart by shape, not just color
+    keys: KeyBindings,         // Keys bound to each action
+    warnings: Vec<SnakeError>, // Problems with the config file, shown once the game starts
+}
+
+/// Parses the value of a command-line option overriding a numeric setting, which must lie in
+/// the range the configuration file accepts for it.
+///
+/// # Arguments
+///
+/// * `flag` - The option the value was given for, used in error messages.
+/// * `key` - The key of the setting in the configuration file.
+/// * `value` - The value following the option, if any.
+fn parse_ranged(flag: &str, key: &str, value: Option<String>) -> Result<i64, SnakeError> {
+    let value = value.ok_or_else(|| SnakeError::Validation(format!("missing value for {flag}")))?;
+    let range = setting_range(key);
+    value
+        .parse()
+        .ok()
+        .filter(|number| range.contains(number))
+        .ok_or_else(|| {
+            SnakeError::Validation(format!(
+                "invalid value '{value}' for {flag}, expected an integer from {} to {}",
+                range.start(),
+                range.end()
+            ))
+        })
+}
+
+/// Parses the command-line arguments, on top of the settings of the configuration file.
+///
+/// Prints a message and exits if an argument is not recognized.
+fn parse_args() -> Args {
+    let (settings, warnings) = Settings::load();
+    let mut difficulty = settings.difficulty;
+    let mut two_player = false;
+    let mut level = None;
+    let mut headless = None;
+    let mut script = None;
+    let mut bot = false;
+    let mut replay = None;
+    let mut random_arena = false;
+    let mut seed = None;
+    let mut trace_overlay = false;
+    let mut screen_width = settings.screen_width;
+    let mut screen_height = settings.screen_height;
+    let mut square_size = settings.square_size;
+    let mut fps = settings.fps;
+    let mut fruit_lifetime = settings.fruit_lifetime;
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--difficulty" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --difficulty".to_string()))
+                .and_then(|value| value.parse())
+                .map(|value| difficulty = value),
+            "--level" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --level".to_string()))
+                .map(|value| level = Some(PathBuf::from(value))),
+            "--seed" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --seed".to_string()))
+                .and_then(|value| {
+                    value.parse().map_err(|_| {
+                        SnakeError::Validation(format!(
+                            "invalid seed '{value}', expected a non-negative integer"
+                        ))
+                    })
+                })
+                .map(|value| seed = Some(value)),
+            "--headless" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --headless".to_string()))
+                .and_then(|value| {
+                    value.parse().map_err(|_| {
+                        SnakeError::Validation(format!(
+                            "invalid tick count '{value}', expected a non-negative integer"
+                        ))
+                    })
+                })
+                .map(|value| headless = Some(value)),
+            "--script" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --script".to_string()))
+                .map(|value| script = Some(PathBuf::from(value))),
+            "--random-arena" => {
+                random_arena = true;
+                Ok(())
+            }
+            "--replay" => args
+                .next()
+                .ok_or_else(|| SnakeError::Validation("missing value for --replay".to_string()))
+                .map(|value| replay = Some(PathBuf::from(value))),
+            "--bot" => {
+                bot = true;
+                Ok(())
+            }
+            "--trace-overlay" => {
+                trace_overlay = true;
+                Ok(())
+            }
+            "--two-player" => {
+                two_player = true;
+                Ok(())
+            }
+            "--width" => parse_ranged("--width", "width", args.next())
+                .map(|value| screen_width = value as i32),
+            "--height" => parse_ranged("--height", "height", args.next())
+                .map(|value| screen_height = value as i32),
+            "--cell-size" => parse_ranged("--cell-size", "cell_size", args.next())
+                .map(|value| square_size = value as i32),
+            "--fps" => parse_ranged("--fps", "fps", args.next()).map(|value| fps = value as u32),
+            "--fruit-lifetime" => parse_ranged("--fruit-lifetime", "fruit_lifetime", args.next())
+                .map(|value| fruit_lifetime = value as u32),
+            "--write-default-config" => {
+                match write_default_config() {
+                    Ok(path) => println!("Wrote the default config to {}", path.display()),
+                    Err(err) => {
+                        eprintln!("snake: failed to write the default config: {err}");
+                        process::exit(1);
+                    }
+                }
+                process::exit(0);
+            }
+            _ => Err(SnakeError::Validation(format!("unknown argument '{arg}'"))),
+        };
+
+        if let Err(err) = result {
+            eprintln!("snake: {err}");
+            eprintln!("usage: snake [--difficulty easy|normal|hard] [--two-player] [--level FILE] [--seed N]");
+            eprintln!(
+                "       [--random-arena] [--trace-overlay] [--bot] [--headless N [--script FILE]]"
+            );
+            eprintln!("       [--replay FILE] [--width N] [--height N] [--cell-size N] [--fps N]");
+            eprintln!("       [--fruit-lifetime SECONDS] [--write-default-config]");
+            process::exit(2);
+        }
+    }
+
+    if script.is_some() && headless.is_none() {
+        eprintln!("snake: --script requires --headless");
+        process::exit(2);
+    }
+    if random_arena && level.is_some() {
+        eprintln!("snake: --random-arena and --level cannot be combined");
+        process::exit(2);
+    }
+    if script.is_some() && bot {
+        eprintln!("snake: --script and --bot cannot be combined");
+        process::exit(2);
+    }
+    // Keep at least two cells each way, so both players get a starting cell of their own
+    if square_size > screen_width.min(screen_height) / 2 {
+        eprintln!(
+            "snake: --cell-size {square_size} is too large for a {screen_width}x{screen_height} window"
+        );
+        process::exit(2);
+    }
+
+    Args {
+        config: GameConfig {
+            two_player,
+            seed,
+            trace_overlay,
+            random_arena,
+            // Lifetimes are set in seconds but counted in ticks
+            fruit_lifetime: (fruit_lifetime > 0)
+                .then(|| (fruit_lifetime as f32 / TICK_DURATION).round() as i32),
+            ..GameConfig::new(difficulty)
+        },
+        level,
+        headless,
+        script,
+        bot,
+        replay,
+        screen_width,
+        screen_height,
+        square_size,
+        fps,
+        volume: settings.volume as f32 / 100.0,
+        music: settings.music,
+        theme: settings.theme,
+        shape_cues: settings.shape_cues,
+        keys: settings.keys,
+        warnings,
+    }
+}
+
+/// Prints why a file the game was asked to use cannot be loaded, and exits.
+///
+/// # Arguments
+///
+/// * `what` - What the file holds, such as `level` or `replay`.
+/// * `path` - The path of the file.
+/// * `err` - The error raised while loading it.
+fn exit_on_load_error(what: &str, path: &Path, err: SnakeError) -> ! {
+    match err {
+        // The message names the file already
+        SnakeError::Parse { .. } => eprintln!("Failed to load {what}: {err}"),
+        _ => eprintln!("Failed to load {what} {}: {err}", path.display()),
+    }
+    process::exit(1);
+}
+
+/// Main function to initialize the game window and run the game loop.
+fn main() {
+    let mut args = parse_args();
+    let mut screen_width = args.screen_width;
+    let mut screen_height = args.screen_height;
+
+    // A replay brings its own settings, level and board, and takes over the inputs
+    let mut config = args.config;
+    let mut level = args.level.clone();
+    let playback = args.replay.as_ref().map(|path| {
+        let replay =
+            load_replay(path).unwrap_or_else(|err| exit_on_load_error("replay", path, err));
+        Playback {
+            replay,
+            next: 0,
+            desync: None,
+        }
+    });
+    if let Some(playback) = &playback {
+        config = playback.replay.config;
+        level = playback.replay.level.clone();
+
+        // Size the window to the recorded board, which the run depends on
+        let (columns, rows) = playback.replay.board;
+        if (
+            screen_width / args.square_size,
+            screen_height / args.square_size,
+        ) != (columns, rows)
+        {
+            screen_width = columns * args.square_size;
+            screen_height = rows * args.square_size;
+        }
+    }
+
+    let mut game_state = GameState::new(screen_width, screen_height, args.square_size);
+    game_state.playback = playback;
+    game_state.volume = args.volume;
+    game_state.set_theme(args.theme);
+    game_state.shape_cues = args.shape_cues;
+    game_state.keys = args.keys;
+    for err in mem::take(&mut args.warnings) {
+        game_state.report("Problem with the config", err);
+    }
+    // A high score that cannot be read should not keep the game from starting
+    game_state.high_score = match load_high_score() {
+        Ok(score) => score,
+        Err(err) => {
+            game_state.report("Failed to load high score", err);
+            0
+        }
+    };
+
+    game_state.init_game(config);
+
+    // A level that cannot be loaded is reported before the window opens
+    if let Some(path) = &level {
+        if let Err(err) = game_state.load_level(path) {
+            exit_on_load_error("level", path, err);
+        }
+    }
+
+    let mut controller: Box<dyn Controller> = match &args.script {
+        Some(path) => Box::new(Script {
+            directions: load_script(path)
+                .unwrap_or_else(|err| exit_on_load_error("script", path, err)),
+        }),
+        // Simulated runs without a script have nobody at the keyboard
+        None if args.bot || args.headless.is_some() => Box::new(GreedyBot),
+        None => Box::new(Keyboard),
+    };
+
+    // Runs nobody plays at the keyboard never touch the stored high score nor the run history
+    if args.bot || args.headless.is_some() {
+        game_state.bot = true;
+        game_state.save_high_score = false;
+    }
+
+    // Simulated runs never open a window
+    if let Some(ticks) = args.headless {
+        run_headless(&mut game_state, ticks, controller.as_mut());
+        return;
+    }
+
+    // Replays skip the title screen
+    if game_state.playback.is_some() {
+        game_state.set_phase(GamePhase::Playing);
+        game_state.skip_to_clip();
+    }
+
+    let (mut rl, thread) = raylib::init()
+        .size(screen_width, screen_height)
+        .title("snake")
+        .resizable()
+        .build();
+
+    rl.set_target_fps(args.fps);
+    // The board keeps its starting size, so the window must stay large enough to show it
+    rl.set_window_min_size(screen_width, screen_height);
+
+    // Sound is optional, so the game carries on silently without an audio device
+    let audio = RaylibAudio::init_audio_device()
+        .ok()
+        .filter(|audio| audio.is_audio_device_ready());
+    let mut soundtrack = audio
+        .as_ref()
+        .map(|audio| Soundtrack::load(audio, args.music.as_deref()));
+
+    while !rl.window_should_close() {
+        if rl.is_window_resized() {
+            screen_width = rl.get_screen_width();
+            screen_height = rl.get_screen_height();
+            game_state.resize(screen_width, screen_height);
+        }
+
+        game_state.update_game(&mut rl, &thread, controller.as_mut());
+        if let Some(soundtrack) = &mut soundtrack {
+            soundtrack.update(&game_state);
+        }
+
+        let mut d = rl.begin_drawing(&thread);
+        game_state.draw_game(&mut d, screen_width, screen_height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell as Counter;
+
+    thread_local! {
+        /// Number of allocations made on the current thread.
+        static ALLOCATIONS: Counter<usize> = const { Counter::new(0) };
+    }
+
+    /// Allocator counting the allocations made on each thread, so a test can check that some
+    /// code allocates nothing while other tests run alongside it.
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Starts a run on the default board that never touches the files on disk.
+    ///
+    /// The run is seeded with 1 unless `config` sets another seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings to play the run with.
+    fn start_run(config: GameConfig) -> GameState {
+        let mut state = GameState::new(800, 450, SQUARE_SIZE);
+        state.save_high_score = false;
+        state.init_game(GameConfig {
+            seed: config.seed.or(Some(1)),
+            ..config
+        });
+        state.set_phase(GamePhase::Playing);
+        state
+    }
+
+    /// Runs a number of ticks without any input.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The game to advance.
+    /// * `ticks` - The number of ticks to run.
+    fn run_ticks(state: &mut GameState, ticks: i32) {
+        for _ in 0..ticks {
+            state.tick(InputState::default());
+        }
+    }
+
+    /// Replaces a player's snake with segments on the given cells, head first.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The game holding the snake.
+    /// * `index` - The index of the player.
+    /// * `cells` - The cells of the segments, head first.
+    /// * `direction` - The direction the snake heads in.
+    fn place_snake(state: &mut GameState, index: usize, cells: &[Cell], direction: Direction) {
+        let head = state.players[index].snake[0];
+        state.players[index].snake = cells
+            .iter()
+            .map(|&position| Snake {
+                position,
+                direction,
+                ..head
+            })
+            .collect();
+    }
+
+    /// Starts a run whose fruits expire after `lifetime` ticks, with the first fruit moved out
+    /// of the snake's way.
+    ///
+    /// # Arguments
+    ///
+    /// * `lifetime` - The number of ticks a fruit lasts.
+    fn start_fruit_run(lifetime: Option<i32>) -> GameState {
+        let mut state = start_run(GameConfig {
+            fruit_lifetime: lifetime,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        run_ticks(&mut state, 1);
+        state.fruit.position = (20, 10);
+        state
+    }
+
+    #[test]
+    fn fruit_expires_on_the_exact_tick() {
+        let mut state = start_fruit_run(Some(10));
+        for _ in 1..10 {
+            run_ticks(&mut state, 1);
+            assert_eq!(state.fruit.position, (20, 10));
+        }
+        assert_eq!(state.puff, None);
+
+        run_ticks(&mut state, 1);
+        assert_eq!(state.puff, Some(((20, 10), 10)));
+        assert!(state.fruit.active);
+        assert_ne!(state.fruit.position, (20, 10));
+        // The timer starts over for the relocated fruit
+        assert_eq!(state.fruit.age, 1);
+    }
+
+    #[test]
+    fn fruit_reached_on_its_last_tick_is_eaten() {
+        // Normal moves the snakes every 6 ticks, so the fruit's last tick is a step
+        let mut state = start_fruit_run(Some(12));
+        run_ticks(&mut state, 11);
+        place_snake(&mut state, 0, &[(19, 10)], Direction::Right);
+
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (20, 10));
+        assert_eq!(state.players[0].snake.len(), 2);
+        assert_eq!(state.puff, None);
+    }
+
+    #[test]
+    fn fruit_timer_freezes_during_pause() {
+        let mut state = start_fruit_run(Some(10));
+        run_ticks(&mut state, 4);
+
+        state.set_phase(GamePhase::Paused);
+        run_ticks(&mut state, 100);
+        assert_eq!(state.fruit.age, 5);
+
+        state.set_phase(GamePhase::Playing);
+        run_ticks(&mut state, 5);
+        assert_eq!(state.fruit.position, (20, 10));
+        run_ticks(&mut state, 1);
+        assert_ne!(state.fruit.position, (20, 10));
+    }
+
+    #[test]
+    fn fruit_without_lifetime_stays_put() {
+        let mut state = start_fruit_run(None);
+        run_ticks(&mut state, 90);
+        assert_eq!(state.fruit.position, (20, 10));
+        assert_eq!(state.puff, None);
+    }
+
+    #[test]
+    fn replay_keeps_fruit_lifetime() {
+        let state = start_fruit_run(Some(300));
+        let replay = Replay::parse(&state.recording.to_text(), Path::new("replay.txt")).unwrap();
+        assert_eq!(replay.config.fruit_lifetime, Some(300));
+    }
+
+    #[test]
+    fn danger_ignores_tail_about_to_vacate() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(
+            &mut state,
+            0,
+            &[(5, 5), (5, 6), (6, 6), (6, 5)],
+            Direction::Right,
+        );
+        state.predict_danger();
+        assert_eq!(state.danger[0], Some(((6, 5), Color::GREEN)));
+    }
+
+    #[test]
+    fn danger_marks_body_still_in_place() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(
+            &mut state,
+            0,
+            &[(5, 5), (5, 4), (6, 4), (7, 4), (7, 5), (7, 6), (6, 6)],
+            Direction::Right,
+        );
+        state.predict_danger();
+        assert_eq!(
+            state.danger,
+            [
+                Some(((6, 5), Color::YELLOW)),
+                Some(((7, 5), Color::RED)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn danger_wraps_around_the_board() {
+        let mut state = start_run(GameConfig::new(Difficulty::Easy));
+        place_snake(&mut state, 0, &[(24, 3)], Direction::Right);
+        state.obstacles = HashSet::from([(1, 3)]);
+        state.predict_danger();
+        assert_eq!(
+            state.danger,
+            [
+                Some(((0, 3), Color::YELLOW)),
+                Some(((1, 3), Color::RED)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn danger_stops_at_solid_walls() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(23, 3)], Direction::Right);
+        state.predict_danger();
+        assert_eq!(
+            state.danger,
+            [
+                Some(((24, 3), Color::YELLOW)),
+                Some(((25, 3), Color::RED)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_of_perpendicular_keys_turns_off_the_heading_first() {
+        for heading in Direction::ALL {
+            for first in Direction::ALL {
+                for second in Direction::ALL {
+                    if first == second || first == second.opposite() {
+                        continue;
+                    }
+                    let (off_heading, along_heading) =
+                        if first.is_horizontal() == heading.is_horizontal() {
+                            (second, first)
+                        } else {
+                            (first, second)
+                        };
+                    assert_eq!(
+                        resolve_chord(heading, &[first, second]),
+                        [off_heading, along_heading],
+                        "heading {heading}, pressed {first} and {second}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chord_of_opposite_keys_is_ignored() {
+        for heading in Direction::ALL {
+            for pressed in Direction::ALL {
+                assert_eq!(resolve_chord(heading, &[pressed, pressed.opposite()]), []);
+            }
+        }
+    }
+
+    #[test]
+    fn chord_keeps_single_keys_and_cancels_opposites_among_three() {
+        for heading in Direction::ALL {
+            for pressed in Direction::ALL {
+                assert_eq!(resolve_chord(heading, &[pressed]), [pressed]);
+
+                let side = pressed.clockwise();
+                assert_eq!(
+                    resolve_chord(heading, &[side, pressed, side.opposite()]),
+                    [pressed]
+                );
+            }
+            assert_eq!(resolve_chord(heading, &Direction::ALL), []);
+        }
+    }
+
+    #[test]
+    fn chord_makes_a_tight_two_step_turn() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        let mut input = InputState::default();
+        input.directions[0] = resolve_chord(Direction::Right, &[Direction::Left, Direction::Up]);
+        state.tick(input);
+        assert_eq!(state.players[0].snake[0].position, (5, 4));
+
+        let interval = state.move_interval();
+        run_ticks(&mut state, interval);
+        assert_eq!(state.players[0].snake[0].position, (4, 4));
+    }
+
+    #[test]
+    fn spawn_safe_zone_is_clipped_at_the_walls() {
+        let state = start_run(GameConfig {
+            two_player: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        let zone: Vec<Cell> = (-1..=state.rows)
+            .flat_map(|row| (-1..=state.columns).map(move |column| (column, row)))
+            .filter(|&cell| !state.is_out_of_bounds(cell) && state.is_in_spawn_safe_zone(cell))
+            .collect();
+        assert_eq!(
+            zone,
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (0, 1),
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (21, 12),
+                (22, 12),
+                (23, 12),
+                (24, 12),
+                (21, 13),
+                (22, 13),
+                (23, 13),
+                (24, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_fruit_avoids_the_spawn_safe_zone() {
+        for seed in 0..200 {
+            let mut state = start_run(GameConfig {
+                seed: Some(seed),
+                ..GameConfig::new(Difficulty::Normal)
+            });
+            run_ticks(&mut state, 1);
+            assert!(
+                !state.is_in_spawn_safe_zone(state.fruit.position),
+                "seed {seed}"
+            );
+        }
+    }
+
+    /// Every phase, for the transition tests.
+    const PHASES: [GamePhase; 5] = [
+        GamePhase::Title,
+        GamePhase::Playing,
+        GamePhase::Paused,
+        GamePhase::GameOver,
+        GamePhase::Won,
+    ];
+
+    /// Every legal transition, as `(from, to)`.
+    const TRANSITIONS: [(GamePhase, GamePhase); 7] = [
+        (GamePhase::Title, GamePhase::Playing),
+        (GamePhase::Playing, GamePhase::Paused),
+        (GamePhase::Paused, GamePhase::Playing),
+        (GamePhase::Playing, GamePhase::GameOver),
+        (GamePhase::GameOver, GamePhase::Playing),
+        (GamePhase::Playing, GamePhase::Won),
+        (GamePhase::Won, GamePhase::Playing),
+    ];
+
+    #[test]
+    fn only_listed_transitions_are_legal() {
+        for from in PHASES {
+            for to in PHASES {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    TRANSITIONS.contains(&(from, to)),
+                    "{from:?} to {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_legal_transition_can_be_taken() {
+        // Starting the run takes the edge out of the title screen
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        for (from, to) in TRANSITIONS.into_iter().skip(1) {
+            if state.phase != from {
+                state.set_phase(from);
+            }
+            state.set_phase(to);
+            assert_eq!(state.phase, to);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "illegal phase transition")]
+    fn illegal_transition_is_rejected() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        state.set_phase(GamePhase::Title);
+    }
+
+    #[test]
+    fn leaving_a_run_drops_leftover_time_and_turns() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        state.accumulator = TICK_DURATION / 2.0;
+        state.pending_directions[0] = vec![Direction::Down];
+
+        state.set_phase(GamePhase::Paused);
+        assert_eq!(state.accumulator, 0.0);
+        assert!(state.pending_directions[0].is_empty());
+    }
+
+    /// Starts a run with the auto-turn at walls assist on and player one's snake on `cells`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cells` - The cells of the snake, head first.
+    /// * `direction` - The direction the snake heads in.
+    fn start_auto_turn_run(cells: &[Cell], direction: Direction) -> GameState {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        state.auto_turn = true;
+        place_snake(&mut state, 0, cells, direction);
+        state
+    }
+
+    #[test]
+    fn auto_turn_takes_the_only_open_side_in_a_corner() {
+        let mut state = start_auto_turn_run(&[(24, 0)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (24, 1));
+
+        let mut state = start_auto_turn_run(&[(24, 13)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (24, 12));
+    }
+
+    #[test]
+    fn auto_turn_prefers_the_longer_runway_then_clockwise() {
+        let mut state = start_auto_turn_run(&[(24, 3)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (24, 4));
+
+        let mut state = start_auto_turn_run(&[(24, 10)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (24, 9));
+
+        // Six cells each way, with the obstacle shortening the way down
+        let mut state = start_auto_turn_run(&[(24, 6)], Direction::Right);
+        state.obstacles = HashSet::from([(24, 13)]);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (24, 7));
+    }
+
+    #[test]
+    fn auto_turn_keeps_heading_when_both_sides_are_blocked() {
+        // The wall closes one side of the corner and the snake's own body the other
+        let mut state = start_auto_turn_run(&[(24, 0), (24, 1), (23, 1)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].direction, Direction::Right);
+        assert_eq!(state.phase, GamePhase::GameOver);
+        assert!(state.hit_wall);
+
+        let mut state = start_auto_turn_run(&[(24, 13), (24, 12), (23, 12)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].direction, Direction::Right);
+        assert_eq!(state.phase, GamePhase::GameOver);
+    }
+
+    /// Returns the line numbers of the parse errors among `errors`, in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `errors` - The errors to look through.
+    fn error_lines(errors: &[SnakeError]) -> Vec<usize> {
+        errors
+            .iter()
+            .filter_map(|err| match err {
+                SnakeError::Parse { line, .. } => Some(*line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn io_errors_convert_and_keep_their_source() {
+        let err = SnakeError::from(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        assert!(matches!(err, SnakeError::Io(_)));
+        assert_eq!(err.to_string(), "denied");
+        assert!(error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn errors_format_for_the_player() {
+        let parse = SnakeError::Parse {
+            file: PathBuf::from("levels/maze.txt"),
+            line: 3,
+            msg: "unexpected 'x'".to_string(),
+        };
+        assert_eq!(parse.to_string(), "levels/maze.txt:3: unexpected 'x'");
+
+        let version = SnakeError::Version {
+            found: "3".to_string(),
+            supported: 2,
+        };
+        assert_eq!(
+            version.to_string(),
+            "written by version 3 of the game, but only up to version 2 is read"
+        );
+
+        let corrupt = SnakeError::Corrupt(PathBuf::from("highscore"));
+        assert_eq!(corrupt.to_string(), "highscore is corrupt");
+        assert_eq!(
+            SnakeError::Validation("no seed".to_string()).to_string(),
+            "no seed"
+        );
+        assert!(error::Error::source(&parse).is_none());
+    }
+
+    #[test]
+    fn only_unusable_files_stop_the_game() {
+        let toasts = [
+            SnakeError::Io(io::Error::other("full")),
+            SnakeError::Parse {
+                file: PathBuf::from("snake.toml"),
+                line: 1,
+                msg: String::new(),
+            },
+            SnakeError::Validation(String::new()),
+        ];
+        for err in toasts {
+            assert_eq!(err.severity(), Severity::Toast, "{err:?}");
+        }
+
+        let dialogs = [
+            SnakeError::Version {
+                found: "2".to_string(),
+                supported: 1,
+            },
+            SnakeError::Corrupt(PathBuf::from("highscore")),
+        ];
+        for err in dialogs {
+            assert_eq!(err.severity(), Severity::Dialog, "{err:?}");
+        }
+    }
+
+    #[test]
+    fn replay_from_another_version_is_rejected() {
+        let path = Path::new("replay.txt");
+        match Replay::parse("snake-replay 3\nseed 1\n", path) {
+            Err(SnakeError::Version { found, supported }) => {
+                assert_eq!((found.as_str(), supported), ("3", REPLAY_VERSION));
+            }
+            other => panic!("expected a version error, got {other:?}"),
+        }
+
+        let err = Replay::parse("hello\n", path).unwrap_err();
+        assert_eq!(err.to_string(), "replay.txt:1: not a replay file");
+    }
+
+    #[test]
+    fn replay_errors_point_at_the_line() {
+        let path = Path::new("replay.txt");
+        let text = "snake-replay 1\nseed 1\n5 pause\n3 jump\n";
+        assert_eq!(
+            Replay::parse(text, path).unwrap_err().to_string(),
+            "replay.txt:4: invalid entry"
+        );
+
+        let text = "snake-replay 1\nseed 1\n5 pause\n3 pause\n";
+        assert_eq!(
+            Replay::parse(text, path).unwrap_err().to_string(),
+            "replay.txt:4: events are out of order"
+        );
+
+        let text = "snake-replay 1\nseed 1\n0 turn 0 sideways\n";
+        assert!(matches!(
+            Replay::parse(text, path),
+            Err(SnakeError::Parse { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn config_problems_point_at_the_line() {
+        let text = "fps = 30\nbogus\nspeed = 3\nvolume = 200\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        assert_eq!(settings.fps, 30);
+        assert_eq!(settings.volume, Settings::default().volume);
+        assert_eq!(error_lines(&problems), [2, 3, 4]);
+        assert_eq!(problems[1].to_string(), "snake.toml:3: unknown key 'speed'");
+    }
+
+    #[test]
+    fn heads_swapping_cells_collide() {
+        let mut state = start_run(GameConfig {
+            two_player: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        place_snake(&mut state, 0, &[(10, 5)], Direction::Right);
+        place_snake(&mut state, 1, &[(11, 5)], Direction::Left);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::GameOver);
+        assert_eq!(state.outcome, Some(Outcome::Draw));
+    }
+
+    #[test]
+    fn head_following_a_tail_out_of_its_cell_survives() {
+        let mut state = start_run(GameConfig {
+            two_player: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        place_snake(&mut state, 0, &[(10, 5)], Direction::Right);
+        place_snake(&mut state, 1, &[(11, 5)], Direction::Right);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (11, 5));
+    }
+
+    #[test]
+    fn auto_turn_avoids_obstacles() {
+        let mut state = start_auto_turn_run(&[(10, 5)], Direction::Right);
+        state.obstacles = HashSet::from([(11, 5)]);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (10, 6));
+
+        // Walls are no danger when wrapping, but obstacles still are
+        let mut state = start_auto_turn_run(&[(24, 5)], Direction::Right);
+        state.config.wall_mode = WallMode::Wrap;
+        state.obstacles = HashSet::from([(0, 5)]);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (24, 6));
+    }
+
+    #[test]
+    fn auto_turn_leaves_a_clear_path_alone() {
+        let mut state = start_auto_turn_run(&[(24, 5)], Direction::Right);
+        state.config.wall_mode = WallMode::Wrap;
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (0, 5));
+    }
+
+    #[test]
+    fn running_into_any_wall_ends_the_run() {
+        let edges = [
+            ((0, 5), Direction::Left),
+            ((24, 5), Direction::Right),
+            ((5, 0), Direction::Up),
+            ((5, 13), Direction::Down),
+        ];
+        for (cell, direction) in edges {
+            let mut state = start_run(GameConfig::new(Difficulty::Normal));
+            place_snake(&mut state, 0, &[cell], direction);
+            run_ticks(&mut state, 1);
+            assert_eq!(state.phase, GamePhase::GameOver, "{direction}");
+            assert!(state.hit_wall, "{direction}");
+        }
+    }
+
+    #[test]
+    fn running_into_its_own_body_ends_the_run() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(
+            &mut state,
+            0,
+            &[(5, 5), (6, 5), (6, 6), (5, 6), (4, 6)],
+            Direction::Down,
+        );
+        run_ticks(&mut state, 1);
+        assert_eq!(state.phase, GamePhase::GameOver);
+        assert!(!state.hit_wall);
+    }
+
+    #[test]
+    fn eating_a_fruit_grows_the_snake() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        state.fruit.position = (6, 5);
+        state.fruit.active = true;
+        run_ticks(&mut state, 1);
+
+        let snake = &state.players[0].snake;
+        assert_eq!(
+            snake
+                .iter()
+                .map(|segment| segment.position)
+                .collect::<Vec<_>>(),
+            [(6, 5), (5, 5)]
+        );
+        assert_eq!(state.players[0].score, state.config.fruit_score);
+        assert!(!state.fruit.active);
+
+        // The tail stays behind the head on the next move
+        let interval = state.move_interval();
+        run_ticks(&mut state, interval);
+        let snake = &state.players[0].snake;
+        assert_eq!(
+            snake
+                .iter()
+                .map(|segment| segment.position)
+                .collect::<Vec<_>>(),
+            [(7, 5), (6, 5)]
+        );
+    }
+
+    #[test]
+    fn reversing_into_the_neck_is_rejected() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5), (4, 5)], Direction::Right);
+        let mut input = InputState::default();
+        input.directions[0] = vec![Direction::Left];
+        state.tick(input);
+
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (6, 5));
+        assert!(state.recording.events.is_empty());
+    }
+
+    #[test]
+    fn nothing_moves_while_paused() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        state.set_phase(GamePhase::Paused);
+        run_ticks(&mut state, 50);
+        assert_eq!(state.tick_counter, 0);
+        assert_eq!(state.players[0].snake[0].position, (5, 5));
+
+        state.set_phase(GamePhase::Playing);
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (6, 5));
+    }
+
+    #[test]
+    fn ticks_allocate_nothing_without_the_trace() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        // The first tick spawns the fruit, which lists the free cells whether tracing or not
+        run_ticks(&mut state, 1);
+
+        let before = ALLOCATIONS.with(Counter::get);
+        let interval = state.move_interval();
+        run_ticks(&mut state, 2 * interval);
+        assert_eq!(ALLOCATIONS.with(Counter::get), before);
+        assert_eq!(state.players[0].snake[0].position, (8, 5));
+
+        // The count does move when something allocates, such as spawning a fruit
+        state.fruit.active = false;
+        run_ticks(&mut state, 1);
+        assert!(ALLOCATIONS.with(Counter::get) > before);
+    }
+
+    #[test]
+    fn trace_marks_candidates_no_head_can_reach() {
+        let mut state = start_run(GameConfig {
+            trace_overlay: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        place_snake(&mut state, 0, &[(5, 5), (4, 5)], Direction::Right);
+        // Wall off the bottom-right corner
+        let corner = (state.columns - 1, state.rows - 1);
+        state.obstacles = HashSet::from([(corner.0 - 1, corner.1), (corner.0, corner.1 - 1)]);
+        run_ticks(&mut state, 1);
+
+        let trace = state.trace.as_ref().unwrap();
+        assert!(trace.candidates.contains(&corner));
+        assert_eq!(trace.unreachable, [corner]);
+    }
+
+    #[test]
+    fn trace_marks_heads_following_a_tail() {
+        let mut state = start_run(GameConfig {
+            trace_overlay: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+        // The head turns down into the cell the tail leaves on the same step
+        let cells = [(5, 5), (6, 5), (6, 6), (5, 6)];
+        place_snake(&mut state, 0, &cells, Direction::Down);
+        run_ticks(&mut state, 1);
+
+        assert_eq!(state.phase, GamePhase::Playing);
+        assert_eq!(state.players[0].snake[0].position, (5, 6));
+        assert_eq!(state.trace.as_ref().unwrap().exempted, [(5, 6)]);
+
+        // Nothing is spared on a tick the snakes stand still
+        run_ticks(&mut state, 1);
+        assert!(state.trace.as_ref().unwrap().exempted.is_empty());
+    }
+
+    /// Starts a run on a random arena generated from `seed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the run.
+    /// * `two_player` - Whether the run has a second snake.
+    fn start_arena_run(seed: u64, two_player: bool) -> GameState {
+        start_run(GameConfig {
+            seed: Some(seed),
+            random_arena: true,
+            two_player,
+            ..GameConfig::new(Difficulty::Normal)
+        })
+    }
+
+    #[test]
+    fn random_arenas_keep_every_open_cell_reachable() {
+        for seed in 0..100 {
+            for two_player in [false, true] {
+                let state = start_arena_run(seed, two_player);
+                assert!(!state.obstacles.is_empty(), "seed {seed}");
+
+                let mut reached = HashSet::from([(0, 0)]);
+                let mut pending = vec![(0, 0)];
+                while let Some(cell) = pending.pop() {
+                    for direction in Direction::ALL {
+                        let next = direction.advance(cell, 1);
+                        if !state.is_out_of_bounds(next)
+                            && !state.obstacles.contains(&next)
+                            && reached.insert(next)
+                        {
+                            pending.push(next);
+                        }
+                    }
+                }
+                let open = (state.columns * state.rows) as usize - state.obstacles.len();
+                assert_eq!(
+                    reached.len(),
+                    open,
+                    "seed {seed}, two players: {two_player}"
+                );
+                assert!(
+                    state
+                        .obstacles
+                        .iter()
+                        .all(|&cell| !state.is_in_spawn_safe_zone(cell)),
+                    "seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn two_player_arenas_are_mirrored() {
+        for seed in 0..100 {
+            let state = start_arena_run(seed, true);
+            for &(column, row) in &state.obstacles {
+                let mirrored = (state.columns - 1 - column, state.rows - 1 - row);
+                assert!(state.obstacles.contains(&mirrored), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn arenas_depend_only_on_the_seed() {
+        for seed in 0..20 {
+            assert_eq!(
+                start_arena_run(seed, false).obstacles,
+                start_arena_run(seed, false).obstacles
+            );
+        }
+    }
+
+    #[test]
+    fn typed_seed_shows_its_arena_and_reroll_drops_it() {
+        let mut state = GameState::new(800, 450, SQUARE_SIZE);
+        state.save_high_score = false;
+        state.init_game(GameConfig {
+            random_arena: true,
+            ..GameConfig::new(Difficulty::Normal)
+        });
+
+        state.type_seed_digit(4);
+        state.type_seed_digit(2);
+        assert_eq!((state.typed_seed, state.seed), (Some(42), 42));
+        assert_eq!(state.obstacles, start_arena_run(42, false).obstacles);
+
+        state.erase_seed_digit();
+        assert_eq!((state.typed_seed, state.seed), (Some(4), 4));
+        state.erase_seed_digit();
+        assert_eq!((state.typed_seed, state.seed), (None, 4));
+
+        state.type_seed_digit(7);
+        state.reroll_arena();
+        assert_eq!((state.typed_seed, state.config.seed), (None, None));
+
+        // A seed too large to extend is left as it is
+        state.typed_seed = Some(u64::MAX / 10 + 1);
+        state.type_seed_digit(0);
+        assert_eq!(state.typed_seed, Some(u64::MAX / 10 + 1));
+    }
+
+    #[test]
+    fn watching_a_replay_leaves_the_high_score_alone() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        // The replay alone keeps the score off the disk
+        state.save_high_score = true;
+        state.playback = Some(Playback {
+            replay: state.recording.clone(),
+            next: 0,
+            desync: None,
+        });
+        state.players[0].score = 50;
+        state.set_phase(GamePhase::GameOver);
+        assert_eq!(state.high_score, 0);
+        assert!(!state.new_high_score);
+    }
+
+    #[test]
+    fn bot_runs_leave_the_high_score_alone() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        // As main sets them up for --bot and --headless
+        state.bot = true;
+        state.save_high_score = false;
+        state.high_score = 20;
+        state.players[0].score = 50;
+        state.set_phase(GamePhase::GameOver);
+        assert_eq!(state.high_score, 20);
+        assert!(!state.new_high_score);
+        assert_eq!(
+            state.hud_items()[0],
+            HudItem::Score {
+                score: 50,
+                best: 20
+            }
+        );
+    }
+
+    #[test]
+    fn move_progress_fills_up_on_the_tick_before_a_step() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        let interval = state.move_interval();
+        assert_eq!(state.move_progress(), 1.0);
+
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (6, 5));
+        assert_eq!(state.move_progress(), 1.0 / interval as f32);
+
+        run_ticks(&mut state, interval - 1);
+        assert_eq!(state.move_progress(), 1.0);
+        assert_eq!(state.players[0].snake[0].position, (6, 5));
+
+        run_ticks(&mut state, 1);
+        assert_eq!(state.players[0].snake[0].position, (7, 5));
+        assert_eq!(state.move_progress(), 1.0 / interval as f32);
+    }
+
+    #[test]
+    fn presses_on_frames_between_ticks_all_count() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        place_snake(&mut state, 0, &[(5, 5)], Direction::Right);
+        state.buffer_turns(&[vec![Direction::Up], Vec::new()]);
+        state.buffer_turns(&[Vec::new(), Vec::new()]);
+        state.buffer_turns(&[vec![Direction::Left], Vec::new()]);
+        assert_eq!(
+            state.pending_directions[0],
+            [Direction::Up, Direction::Left]
+        );
+
+        let input = InputState {
+            directions: mem::take(&mut state.pending_directions),
+            ..InputState::default()
+        };
+        state.tick(input);
+        assert_eq!(state.players[0].snake[0].position, (5, 4));
+        let interval = state.move_interval();
+        run_ticks(&mut state, interval);
+        assert_eq!(state.players[0].snake[0].position, (4, 4));
+    }
+
+    /// Returns a path in a scratch directory for this test process, removing any file left
+    /// there by an earlier run.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the file, unique to the test using it.
+    fn scratch_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("snake-raylib-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// Returns a run summary that can be told apart from others by its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - The length the run ended with.
+    fn summary(length: usize) -> RunSummary {
+        RunSummary {
+            seconds: 20.0,
+            length,
+            hit_wall: false,
+        }
+    }
+
+    #[test]
+    fn first_runs_from_two_games_are_merged() {
+        let path = scratch_path("first-runs-merged");
+        // Both games load an empty history before either of them saves
+        assert_eq!(load_first_runs(&path).unwrap().0.len(), 0);
+        assert!(save_first_run(&path, summary(4)).unwrap().is_none());
+        assert!(save_first_run(&path, summary(7)).unwrap().is_none());
+
+        let (runs, done) = load_first_runs(&path).unwrap();
+        let lengths: Vec<usize> = runs.iter().map(|run| run.length).collect();
+        assert_eq!(lengths, [4, 7]);
+        assert!(!done);
+    }
+
+    #[test]
+    fn first_runs_saved_at_the_same_time_are_all_kept() {
+        let path = scratch_path("first-runs-concurrent");
+        let handles: Vec<_> = (0..SUGGESTION_RUNS)
+            .map(|length| {
+                let path = path.clone();
+                std::thread::spawn(move || save_first_run(&path, summary(length)).unwrap())
+            })
+            .collect();
+        let completed: Vec<_> = handles
+            .into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .collect();
+        assert_eq!(completed.len(), 1, "exactly one game makes the suggestion");
+
+        let (runs, done) = load_first_runs(&path).unwrap();
+        let mut lengths: Vec<usize> = runs.iter().map(|run| run.length).collect();
+        lengths.sort_unstable();
+        assert_eq!(lengths, (0..SUGGESTION_RUNS).collect::<Vec<_>>());
+        assert!(done);
+    }
+
+    #[test]
+    fn first_runs_stop_once_the_suggestion_is_made() {
+        let path = scratch_path("first-runs-done");
+        for length in 0..SUGGESTION_RUNS {
+            save_first_run(&path, summary(length)).unwrap();
+        }
+        assert!(save_first_run(&path, summary(99)).unwrap().is_none());
+
+        let (runs, done) = load_first_runs(&path).unwrap();
+        assert_eq!(runs.len(), SUGGESTION_RUNS);
+        assert!(done);
+    }
+
+    #[test]
+    fn corrupt_first_runs_are_overwritten() {
+        let path = scratch_path("first-runs-corrupt");
+        fs::write(&path, "not a run\n").unwrap();
+        assert!(matches!(
+            load_first_runs(&path),
+            Err(SnakeError::Corrupt(_))
+        ));
+
+        save_first_run(&path, summary(5)).unwrap();
+        assert_eq!(load_first_runs(&path).unwrap().0.len(), 1);
+    }
+
+    /// Returns a history of runs that all lasted as long and ended the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - The time each run lasted.
+    /// * `lengths` - The length each run ended with.
+    /// * `wall_deaths` - How many of the runs, first ones first, ended at a wall.
+    fn history(seconds: f32, lengths: &[usize], wall_deaths: usize) -> Vec<RunSummary> {
+        lengths
+            .iter()
+            .enumerate()
+            .map(|(index, &length)| RunSummary {
+                seconds,
+                length,
+                hit_wall: index < wall_deaths,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runs_ending_at_once_suggest_easy() {
+        let runs = history(8.0, &[3, 4, 3], 0);
+        assert_eq!(suggest_difficulty(&runs), Some(Difficulty::Easy));
+    }
+
+    #[test]
+    fn short_runs_into_walls_suggest_easy() {
+        assert_eq!(
+            suggest_difficulty(&history(25.0, &[6, 7, 5], 2)),
+            Some(Difficulty::Easy)
+        );
+        assert_eq!(suggest_difficulty(&history(25.0, &[6, 7, 5], 1)), None);
+        assert_eq!(suggest_difficulty(&history(40.0, &[6, 7, 5], 3)), None);
+    }
+
+    #[test]
+    fn long_runs_with_a_long_snake_suggest_hard() {
+        assert_eq!(
+            suggest_difficulty(&history(90.0, &[12, 20, 16], 0)),
+            Some(Difficulty::Hard)
+        );
+        assert_eq!(suggest_difficulty(&history(90.0, &[8, 10, 12], 0)), None);
+    }
+
+    #[test]
+    fn no_runs_suggest_nothing() {
+        assert_eq!(suggest_difficulty(&[]), None);
+    }
+
+    #[test]
+    fn suggestion_skips_the_difficulty_being_played() {
+        let runs = history(90.0, &[20, 20, 20], 0);
+        let mut state = GameState::new(800, 450, SQUARE_SIZE);
+        state.config.difficulty = Difficulty::Normal;
+        state.offer_suggestion(&runs);
+        assert_eq!(state.suggestion, Some(Difficulty::Hard));
+
+        state.config.difficulty = Difficulty::Hard;
+        state.offer_suggestion(&runs);
+        assert_eq!(state.suggestion, None);
+    }
+
+    #[test]
+    fn only_solo_runs_at_the_keyboard_are_first_runs() {
+        let mut state = GameState::new(800, 450, SQUARE_SIZE);
+        assert!(state.counts_as_first_run());
+
+        state.bot = true;
+        assert!(!state.counts_as_first_run());
+
+        state.bot = false;
+        state.config.two_player = true;
+        assert!(!state.counts_as_first_run());
+    }
+
+    #[test]
+    fn run_rng_draws_the_same_numbers_as_std_rng() {
+        let mut plain = StdRng::seed_from_u64(7);
+        let mut counted = RunRng::new(7);
+        for bound in 1..200_usize {
+            assert_eq!(plain.gen_range(0..bound), counted.gen_range(0..bound));
+            assert_eq!(plain.gen_bool(0.4), counted.gen_bool(0.4));
+        }
+
+        let mut resumed = RunRng::resume(7, counted.draws);
+        for _ in 0..100 {
+            assert_eq!(counted.next_u64(), resumed.next_u64());
+        }
+    }
+
+    /// Returns a hash of everything that decides how a run plays on.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The game to hash.
+    fn state_hash(state: &GameState) -> u64 {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        state.snapshot().to_text().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn clip_plays_back_to_the_states_of_the_live_run() {
+        let mut state = start_run(GameConfig {
+            seed: Some(6),
+            ..GameConfig::new(Difficulty::Easy)
+        });
+        let mut bot = GreedyBot;
+        let mut hashes = HashMap::new();
+        while state.tick_counter < 2400 {
+            hashes.insert(state.tick_counter, state_hash(&state));
+            // A pause inside the clip drops the buffered turns, and must be replayed too
+            if state.tick_counter == 1500 {
+                state.set_phase(GamePhase::Paused);
+                state.set_phase(GamePhase::Playing);
+            }
+            let mut input = InputState::default();
+            input.directions[0] = bot.decide(&state, &input);
+            state.tick(input);
+            assert_eq!(state.phase, GamePhase::Playing);
+        }
+        hashes.insert(state.tick_counter, state_hash(&state));
+        // Pausing right before saving puts an input on the clip's last tick
+        state.set_phase(GamePhase::Paused);
+
+        let clip = state.clip();
+        assert_eq!(clip.clip, Some((2400 - clip_ticks(), 2400)));
+        assert!(state.clip_snapshots.len() <= clip_ticks() as usize / 300 + 2);
+        let text = clip.to_text();
+        let replay = Replay::parse(&text, Path::new("clip.txt")).unwrap();
+        assert!(replay
+            .snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.tick == 600));
+
+        let mut playback = GameState::new(800, 450, SQUARE_SIZE);
+        playback.save_high_score = false;
+        playback.playback = Some(Playback {
+            replay: replay.clone(),
+            next: 0,
+            desync: None,
+        });
+        playback.init_game(replay.config);
+        playback.set_phase(GamePhase::Playing);
+        playback.skip_to_clip();
+        while playback.phase == GamePhase::Playing {
+            assert_eq!(
+                state_hash(&playback),
+                hashes[&playback.tick_counter],
+                "tick {}",
+                playback.tick_counter
+            );
+            playback.tick(InputState::default());
+        }
+
+        assert_eq!(playback.tick_counter, 2400);
+        assert_eq!(state_hash(&playback), hashes[&2400]);
+        assert_eq!(playback.playback.unwrap().desync, None);
+    }
+
+    #[test]
+    fn clip_snapshot_must_fit_the_run() {
+        let path = Path::new("clip.txt");
+        let text = "snake-replay 2\nseed 1\nsnapshot 300 12 solid false\nsnake 0 4 up 3,3 3,4\n\
+                    queue 0 left\nfruit 7 2 40\n";
+        let replay = Replay::parse(text, path).unwrap();
+        let snapshot = replay.snapshot.unwrap();
+        assert_eq!(snapshot.snakes[0].cells, [(3, 3), (3, 4)]);
+        assert_eq!(snapshot.snakes[0].queue, [Direction::Left]);
+        assert_eq!(snapshot.fruit, Some(((7, 2), 40)));
+        assert_eq!(snapshot.to_text(), text["snake-replay 2\nseed 1\n".len()..]);
+
+        let err = Replay::parse(&format!("{text}two-player true\n"), path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "clip.txt has a snapshot of 1 snakes for 2 players"
+        );
+        let err = Replay::parse(&format!("{text}299 pause\n"), path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "clip.txt has events from before its snapshot"
+        );
+        let err = Replay::parse("snake-replay 2\nseed 1\nsnake 0 4 up 3,3\n", path).unwrap_err();
+        assert_eq!(err.to_string(), "clip.txt:3: invalid entry");
+    }
+
+    #[test]
+    fn config_comments_stop_at_quotes() {
+        let text = "music = \"songs/#1.ogg\" # favorite\ntheme = \"dark\"# inline\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(settings.music, Some(PathBuf::from("songs/#1.ogg")));
+        assert_eq!(settings.theme.name, "dark");
+        assert_eq!(strip_comment("# only a comment"), "");
+        assert_eq!(
+            strip_comment(r##"a = "say \"#hi\"" # b"##),
+            r##"a = "say \"#hi\"" "##
+        );
+    }
+
+    #[test]
+    fn config_rejects_values_out_of_range() {
+        let text = "width = 100\nheight = 300\nvolume = 101\nfps = 0\nfruit_lifetime = 5\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        let defaults = Settings::default();
+        assert_eq!(settings.screen_width, defaults.screen_width);
+        assert_eq!(settings.screen_height, 300);
+        assert_eq!(settings.volume, defaults.volume);
+        assert_eq!(settings.fps, defaults.fps);
+        assert_eq!(settings.fruit_lifetime, 5);
+        assert_eq!(error_lines(&problems), [1, 3, 4]);
+        assert_eq!(
+            problems[1].to_string(),
+            "snake.toml:3: 'volume' must be between 0 and 100, using the default"
+        );
+
+        // A cell size in range can still be too large for the window
+        let (settings, problems) =
+            Settings::parse("height = 200\n\ncell_size = 120\n", Path::new("snake.toml"));
+        assert_eq!(settings.square_size, defaults.square_size);
+        assert_eq!(settings.screen_height, defaults.screen_height);
+        assert_eq!(error_lines(&problems), [3]);
+    }
+
+    #[test]
+    fn options_accept_the_ranges_of_the_config_file() {
+        let parse = |flag: &str, key: &str, value: &str| {
+            parse_ranged(flag, key, Some(value.to_string())).ok()
+        };
+        assert_eq!(parse("--width", "width", "7680"), Some(7680));
+        assert_eq!(parse("--width", "width", "100000"), None);
+        assert_eq!(parse("--fps", "fps", "9"), None);
+        assert_eq!(parse("--cell-size", "cell_size", "2000000000"), None);
+        assert_eq!(parse("--fruit-lifetime", "fruit_lifetime", "0"), Some(0));
+        assert_eq!(
+            parse("--fruit-lifetime", "fruit_lifetime", "4294967295"),
+            None
+        );
+        assert!(parse_ranged("--fps", "fps", None).is_err());
+    }
+
+    #[test]
+    fn config_binds_keys_in_their_section() {
+        let text =
+            "theme = \"dark\"\n[keys]\nup = \"u\"\ntheme = \"F\"\nplayer_two_left = \"SPACE\"\n\
+                    jump = \"J\"\npause = \"F12\"\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        assert_eq!(settings.theme.name, "dark");
+        assert_eq!(settings.keys.steer[0][0], KeyboardKey::KEY_U);
+        assert_eq!(settings.keys.cycle_theme, KeyboardKey::KEY_F);
+        assert_eq!(settings.keys.steer[1][2], KeyboardKey::KEY_SPACE);
+        assert_eq!(settings.keys.pause, KeyBindings::default().pause);
+        assert_eq!(error_lines(&problems), [6, 7]);
+        assert_eq!(
+            problems[0].to_string(),
+            "snake.toml:6: unknown action 'jump'"
+        );
+
+        let (_, problems) = Settings::parse("[colors]\nfps = 30\n", Path::new("snake.toml"));
+        assert_eq!(
+            problems[0].to_string(),
+            "snake.toml:1: unknown section 'colors'"
+        );
+    }
+
+    #[test]
+    fn config_rejects_clashing_and_reserved_keys() {
+        let text = "[keys]\nup = \"I\"\npause = \"W\"\nrestart = \"5\"\nsave_replay = \"D\"\n\
+                    theme = \"X\"\nsave_clip = \"X\"\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        let defaults = KeyBindings::default();
+        // Steering right only happens during a run and saving the replay after it
+        assert_eq!(settings.keys.save_replay, KeyboardKey::KEY_D);
+        assert_eq!(settings.keys.steer[0][0], defaults.steer[0][0]);
+        assert_eq!(settings.keys.pause, defaults.pause);
+        assert_eq!(settings.keys.restart, defaults.restart);
+        assert_eq!(settings.keys.cycle_theme, defaults.cycle_theme);
+        assert_eq!(settings.keys.save_clip, defaults.save_clip);
+        // Pause only clashes once up falls back to W
+        assert_eq!(error_lines(&problems), [2, 4, 6, 7, 3]);
+        assert_eq!(
+            problems[0].to_string(),
+            "snake.toml:2: 'up' cannot share I with 'indicator', using the default"
+        );
+        assert_eq!(
+            problems[1].to_string(),
+            "snake.toml:4: 5 types seeds and cannot be bound to 'restart', using the default"
+        );
+
+        // Swapping two keys is fine, and a key read on every screen clashes with any action
+        let text = "[keys]\nup = \"P\"\npause = \"W\"\nindicator = \"L\"\n";
+        let (settings, problems) = Settings::parse(text, Path::new("snake.toml"));
+        assert_eq!(settings.keys.steer[0][0], KeyboardKey::KEY_P);
+        assert_eq!(settings.keys.pause, KeyboardKey::KEY_W);
+        assert_eq!(settings.keys.toggle_indicator, defaults.toggle_indicator);
+        assert_eq!(error_lines(&problems), [4]);
+    }
+
+    #[test]
+    fn config_template_loads_as_the_defaults() {
+        let (settings, problems) = Settings::parse(&Settings::template(), Path::new("snake.toml"));
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(settings.keys, KeyBindings::default());
+        assert_eq!(settings.volume, Settings::default().volume);
+    }
+
+    #[test]
+    fn saved_settings_stay_out_of_the_keys_section() {
+        let template = Settings::template();
+        let contents = with_setting(&template, "theme", "\"dark\"");
+        let (settings, problems) = Settings::parse(&contents, Path::new("snake.toml"));
+        assert!(problems.is_empty(), "{problems:?}");
+        assert_eq!(settings.theme.name, "dark");
+        assert_eq!(settings.keys, KeyBindings::default());
+
+        let contents = with_setting("fps = 30\n[keys]\npause = \"Q\"\n", "theme", "\"dark\"");
+        assert_eq!(
+            contents,
+            "fps = 30\ntheme = \"dark\"\n\n[keys]\npause = \"Q\"\n"
+        );
+        assert_eq!(with_setting("fps = 30\n", "fps", "40"), "fps = 40\n");
+    }
+
+    #[test]
+    fn solo_hud_and_pause_summary() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        state.high_score = 10;
+        state.players[0].score = 7;
+
+        assert_eq!(
+            state.hud_text(),
+            (
+                "SCORE: 7   BEST: 10   LEVEL: 1   NORMAL   WALLS: SOLID".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            state.pause_summary(),
+            [
+                "3 POINTS SHORT OF YOUR BEST OF 10",
+                "LEVEL 1 - 5 FRUITS TO THE NEXT",
+                "PLAYING ON NORMAL",
+                "WALLS: SOLID - PRESS [M] TO CHANGE",
+                "PRESS [P] TO RESUME",
+            ]
+        );
+    }
+
+    #[test]
+    fn two_player_hud_and_pause_summary() {
+        let mut state = start_run(GameConfig {
+            two_player: true,
+            ..GameConfig::new(Difficulty::Hard)
+        });
+        state.players[0].score = 30;
+        state.players[1].score = 50;
+        let cells = [(5, 5), (4, 5), (3, 5), (2, 5), (1, 5)];
+        place_snake(&mut state, 1, &cells, Direction::Right);
+
+        assert_eq!(
+            state.hud_text(),
+            (
+                "P1: 30   P2: 50   LEVEL: 1   HARD   WALLS: SOLID".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            state.pause_summary(),
+            [
+                "PLAYER 2 LEADS 50 TO 30",
+                "LEVEL 1 - 1 FRUIT TO THE NEXT",
+                "PLAYING ON HARD",
+                "WALLS: SOLID - PRESS [M] TO CHANGE",
+                "PRESS [P] TO RESUME",
+            ]
+        );
+    }
+
+    #[test]
+    fn playback_hud_and_pause_summary() {
+        let mut state = start_run(GameConfig::new(Difficulty::Easy));
+        state.playback = Some(Playback {
+            replay: state.recording.clone(),
+            next: 0,
+            desync: None,
+        });
+
+        assert_eq!(
+            state.hud_text(),
+            (
+                "SCORE: 0   BEST: 0   LEVEL: 1   EASY   WALLS: WRAP".to_string(),
+                Some("REPLAY".to_string())
+            )
+        );
+        assert_eq!(
+            state.pause_summary(),
+            [
+                "LEVEL 1 - 5 FRUITS TO THE NEXT",
+                "PLAYING ON EASY",
+                "WALLS: WRAP",
+                "WATCHING A SAVED RUN",
+                "PRESS [P] TO RESUME",
+            ]
+        );
+
+        if let Some(playback) = &mut state.playback {
+            playback.replay.clip = Some((0, 600));
+        }
+        assert_eq!(state.hud_text().1, Some("CLIP".to_string()));
+        assert_eq!(state.pause_summary()[3], "WATCHING A SAVED CLIP");
+    }
+
+    #[test]
+    fn bot_hud_and_pause_summary() {
+        let mut state = start_run(GameConfig::new(Difficulty::Normal));
+        state.bot = true;
+        state.high_score = 10;
+        state.players[0].score = 12;
+
+        assert_eq!(
+            state.hud_text(),
+            (
+                "SCORE: 12   BEST: 10   LEVEL: 1   NORMAL   WALLS: SOLID".to_string(),
+                Some("BOT".to_string())
+            )
+        );
+        assert_eq!(
+            state.pause_summary(),
+            [
+                "12 BEATS YOUR BEST OF 10",
+                "LEVEL 1 - 5 FRUITS TO THE NEXT",
+                "PLAYING ON NORMAL",
+                "WALLS: SOLID - PRESS [M] TO CHANGE",
+                "THE BOT IS STEERING",
+                "PRESS [P] TO RESUME",
+            ]
+        );
     }
 }
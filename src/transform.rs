@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: BSD-3-Clause
+// Copyright (c) 2024 Hitalo M. <https://github.com/HitaloM>
+
+use std::ops::Range;
+
+use raylib::prelude::*;
+
+use crate::Cell;
+
+/// Maps the cells of the board to positions on the screen and back.
+///
+/// Each cell is `cell_size` world units wide, with the board's top-left corner at `origin` in
+/// the world. The camera is the world position shown at the top-left corner of the viewport,
+/// which is drawn `scale` times larger in a rectangle of the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldTransform {
+    cell_size: f32,      // Size of each cell, in world units
+    board: Cell,         // Number of columns and rows of the board
+    origin: Vector2,     // World position of the board's top-left corner
+    camera: Vector2,     // World position shown at the viewport's top-left corner
+    scale: f32,          // Screen pixels per world unit
+    viewport: Rectangle, // Part of the screen the world is drawn in
+}
+
+impl WorldTransform {
+    /// Creates a transform from all of its parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell_size` - The size of each cell, in world units.
+    /// * `board` - The number of columns and rows of the board.
+    /// * `origin` - The world position of the board's top-left corner.
+    /// * `camera` - The world position shown at the viewport's top-left corner.
+    /// * `scale` - The number of screen pixels per world unit.
+    /// * `viewport` - The part of the screen the world is drawn in.
+    pub fn new(
+        cell_size: f32,
+        board: Cell,
+        origin: Vector2,
+        camera: Vector2,
+        scale: f32,
+        viewport: Rectangle,
+    ) -> Self {
+        WorldTransform {
+            cell_size,
+            board,
+            origin,
+            camera,
+            scale,
+            viewport,
+        }
+    }
+
+    /// Creates a transform drawing the board unscaled in the middle of the window, splitting
+    /// the leftover pixels between both sides.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell_size` - The size of each cell, in pixels.
+    /// * `board` - The number of columns and rows of the board.
+    /// * `screen_width` - The width of the game window.
+    /// * `screen_height` - The height of the game window.
+    pub fn centered(cell_size: i32, board: Cell, screen_width: i32, screen_height: i32) -> Self {
+        let origin = Vector2::new(
+            (screen_width - board.0 * cell_size) as f32 / 2.0,
+            (screen_height - board.1 * cell_size) as f32 / 2.0,
+        );
+        let viewport = Rectangle::new(0.0, 0.0, screen_width as f32, screen_height as f32);
+
+        WorldTransform::new(
+            cell_size as f32,
+            board,
+            origin,
+            Vector2::zero(),
+            1.0,
+            viewport,
+        )
+    }
+
+    /// Returns the top-left corner of a cell on the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to convert.
+    pub fn cell_to_screen(&self, (column, row): Cell) -> Vector2 {
+        let world = self.origin + Vector2::new(column as f32, row as f32) * self.cell_size;
+        Vector2::new(self.viewport.x, self.viewport.y) + (world - self.camera) * self.scale
+    }
+
+    /// Returns the center of a cell on the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to convert.
+    pub fn cell_center(&self, cell: Cell) -> Vector2 {
+        self.cell_to_screen(cell) + self.cell_extent() / 2.0
+    }
+
+    /// Returns the area a cell covers on the screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `cell` - The cell to convert.
+    pub fn cell_rectangle(&self, cell: Cell) -> Rectangle {
+        let corner = self.cell_to_screen(cell);
+        let extent = self.cell_extent();
+        Rectangle::new(corner.x, corner.y, extent.x, extent.y)
+    }
+
+    /// Returns the width and height of a cell on the screen.
+    pub fn cell_extent(&self) -> Vector2 {
+        let size = self.cell_size * self.scale;
+        Vector2::new(size, size)
+    }
+
+    /// Returns the cell of the board under a point of the screen, if the point is on the board
+    /// and inside the viewport.
+    ///
+    /// Only built for the tests until something points at the board, such as a mouse.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The position on the screen.
+    #[cfg(test)]
+    pub fn screen_to_cell(&self, point: Vector2) -> Option<Cell> {
+        let inside = |value: f32, start: f32, length: f32| (start..start + length).contains(&value);
+        if !inside(point.x, self.viewport.x, self.viewport.width)
+            || !inside(point.y, self.viewport.y, self.viewport.height)
+        {
+            return None;
+        }
+
+        let position = self.board_position(point);
+        let cell = (position.x.floor() as i32, position.y.floor() as i32);
+        let on_board = (0..self.board.0).contains(&cell.0) && (0..self.board.1).contains(&cell.1);
+        on_board.then_some(cell)
+    }
+
+    /// Returns the columns and rows of the cells of the board showing at least partly in the
+    /// viewport.
+    ///
+    /// A cell touching the viewport only along its edge does not show.
+    pub fn visible_cell_range(&self) -> (Range<i32>, Range<i32>) {
+        let start = self.board_position(Vector2::new(self.viewport.x, self.viewport.y));
+        let end = self.board_position(Vector2::new(
+            self.viewport.x + self.viewport.width,
+            self.viewport.y + self.viewport.height,
+        ));
+        let range = |start: f32, end: f32, count: i32| {
+            let first = (start.floor() as i32).clamp(0, count);
+            let last = (end.ceil() as i32).clamp(first, count);
+            first..last
+        };
+
+        (
+            range(start.x, end.x, self.board.0),
+            range(start.y, end.y, self.board.1),
+        )
+    }
+
+    /// Returns the position of a point of the screen on the board, counted in cells from its
+    /// top-left corner.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The position on the screen.
+    fn board_position(&self, point: Vector2) -> Vector2 {
+        let viewport = Vector2::new(self.viewport.x, self.viewport.y);
+        ((point - viewport) / self.scale + self.camera - self.origin) / self.cell_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a transform with a camera, a scale and a viewport that are all uneven.
+    fn uneven() -> WorldTransform {
+        WorldTransform::new(
+            10.0,
+            (20, 12),
+            Vector2::new(3.5, 7.0),
+            Vector2::new(25.0, -15.0),
+            2.0,
+            Rectangle::new(11.0, 5.0, 201.0, 161.0),
+        )
+    }
+
+    #[test]
+    fn centered_board_splits_the_leftover_pixels() {
+        let transform = WorldTransform::centered(31, (25, 14), 800, 450);
+        assert_eq!(transform.cell_to_screen((0, 0)), Vector2::new(12.5, 8.0));
+        assert_eq!(transform.cell_to_screen((2, 1)), Vector2::new(74.5, 39.0));
+        assert_eq!(transform.cell_center((0, 0)), Vector2::new(28.0, 23.5));
+        assert_eq!(transform.visible_cell_range(), (0..25, 0..14));
+    }
+
+    #[test]
+    fn cells_round_trip_through_the_screen() {
+        let transform = uneven();
+        assert_eq!(transform.visible_cell_range(), (2..13, 0..6));
+
+        // Column 12 shows, but its center is past the viewport's right edge
+        for column in 2..12 {
+            for row in 0..6 {
+                let center = transform.cell_center((column, row));
+                assert_eq!(transform.screen_to_cell(center), Some((column, row)));
+            }
+        }
+    }
+
+    #[test]
+    fn cell_corners_belong_to_their_own_cell() {
+        let transform = uneven();
+        let corner = transform.cell_to_screen((5, 3));
+        assert_eq!(corner, Vector2::new(68.0, 109.0));
+        assert_eq!(transform.screen_to_cell(corner), Some((5, 3)));
+
+        let before = corner - Vector2::new(0.5, 0.5);
+        assert_eq!(transform.screen_to_cell(before), Some((4, 2)));
+    }
+
+    #[test]
+    fn points_off_the_board_or_viewport_have_no_cell() {
+        let transform = uneven();
+        // Above the board, though inside the viewport
+        assert_eq!(
+            transform.screen_to_cell(transform.cell_center((5, -1))),
+            None
+        );
+        // On the board, though past the viewport's right edge
+        assert_eq!(
+            transform.screen_to_cell(transform.cell_center((19, 3))),
+            None
+        );
+    }
+
+    #[test]
+    fn cells_at_the_viewport_edge_show_only_if_they_overlap_it() {
+        let transform = WorldTransform::new(
+            10.0,
+            (20, 12),
+            Vector2::new(3.5, 7.0),
+            Vector2::new(23.5, -15.0),
+            2.0,
+            Rectangle::new(11.0, 5.0, 201.0, 144.0),
+        );
+        assert_eq!(transform.visible_cell_range(), (2..13, 0..5));
+
+        // Column 1 ends on the left edge and row 5 starts on the bottom one, so neither shows,
+        // while column 12 sticks out past the right edge by all but a pixel and still does
+        let viewport = transform.viewport;
+        assert_eq!(transform.cell_to_screen((2, 0)).x, viewport.x);
+        assert_eq!(
+            transform.cell_to_screen((0, 5)).y,
+            viewport.y + viewport.height
+        );
+        assert_eq!(
+            transform.cell_to_screen((12, 0)).x,
+            viewport.x + viewport.width - 1.0
+        );
+    }
+
+    #[test]
+    fn visible_range_stays_on_the_board() {
+        // A window too small for the board cuts it on every side
+        let transform = WorldTransform::centered(31, (10, 5), 200, 100);
+        assert_eq!(transform.visible_cell_range(), (1..9, 0..5));
+
+        let far = WorldTransform::new(
+            10.0,
+            (4, 4),
+            Vector2::zero(),
+            Vector2::new(500.0, 500.0),
+            1.0,
+            Rectangle::new(0.0, 0.0, 100.0, 100.0),
+        );
+        let (columns, rows) = far.visible_cell_range();
+        assert!(columns.is_empty() && rows.is_empty());
+    }
+}